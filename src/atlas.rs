@@ -0,0 +1,126 @@
+//! Texture arrays as one grid image instead of `name#NN` files. Slices are
+//! placed left to right, top to bottom, and copied byte for byte so that a
+//! round trip doesn't change them.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+
+use crate::dxtex::DXImage;
+use crate::files::base_name;
+use crate::formats::ImageFormat;
+use crate::prelude::*;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AtlasLayout {
+    pub columns: usize,
+    pub rows:    usize,
+    pub slices:  usize,
+}
+
+impl AtlasLayout {
+    #[must_use]
+    pub fn new(columns: usize, slices: usize) -> Self {
+        let columns = columns.max(1).min(slices.max(1));
+
+        Self {
+            columns,
+            rows: (slices + columns - 1) / columns,
+            slices,
+        }
+    }
+
+    /// The slice size for an atlas of `width` by `height`, if it divides.
+    pub fn slice_size(&self, width: usize, height: usize) -> Result<(usize, usize)> {
+        if width % self.columns == 0 && height % self.rows == 0 {
+            Ok((width / self.columns, height / self.rows))
+        } else {
+            error_message(format!(
+                "A {width}x{height} image can't be split into {} columns and {} rows",
+                self.columns, self.rows
+            ))
+        }
+    }
+}
+
+/// The image format an atlas of `format` is saved as instead of
+/// `image_format`. DDS would keep the array, so it is replaced by TGA, and LUT
+/// strips are PNG files that photo editors open.
+#[must_use]
+pub fn image_format(format: &TextureFormat, image_format: ImageFormat) -> ImageFormat {
+    match image_format {
+        ImageFormat::Dds if format.is_lut() => ImageFormat::Png,
+        ImageFormat::Dds => ImageFormat::Tga,
+        image_format => image_format,
+    }
+}
+
+/// The file an array exported as `output_file` is written to as an atlas.
+#[must_use]
+pub fn atlas_file(output_file: &Utf8Path, image_format: ImageFormat) -> Utf8PathBuf {
+    let ext = image_format.extensions_str().first().copied().unwrap_or("tga");
+    Utf8PathBuf::from(base_name(output_file)).with_extension(ext)
+}
+
+/// Places every slice of `image` in one image.
+pub fn to_atlas(image: &DXImage, layout: AtlasLayout) -> Result<DXImage> {
+    let metadata = image.metadata()?;
+    let (width, height) = (metadata.width, metadata.height);
+    let bpp = bytes_per_pixel(image, width, height)?;
+
+    let atlas_width = width * layout.columns;
+    let mut buffer = vec![0_u8; atlas_width * height * layout.rows * bpp];
+    for slice in 0 .. metadata.arraySize {
+        let data = image.image(slice * metadata.mipLevels)?;
+        let (x, y) = (slice % layout.columns * width, slice / layout.columns * height);
+        for (row, line) in data.chunks_exact(width * bpp).enumerate() {
+            let start = ((y + row) * atlas_width + x) * bpp;
+            buffer[start .. start + line.len()].copy_from_slice(line);
+        }
+    }
+
+    DXImage::new_2d(metadata.format, atlas_width, height * layout.rows, 1, 1, &buffer)
+}
+
+/// Cuts an uncompressed atlas back into an array.
+pub fn from_atlas(image: &DXImage, layout: AtlasLayout) -> Result<DXImage> {
+    let metadata = image.metadata()?;
+    let (width, height) = layout.slice_size(metadata.width, metadata.height)?;
+    let bpp = bytes_per_pixel(image, metadata.width, metadata.height)?;
+    let data = image.image(0)?;
+
+    let mut buffer = Vec::with_capacity(width * height * layout.slices * bpp);
+    for slice in 0 .. layout.slices {
+        let (x, y) = (slice % layout.columns * width, slice / layout.columns * height);
+        for row in y .. y + height {
+            let start = (row * metadata.width + x) * bpp;
+            buffer.extend_from_slice(&data[start .. start + width * bpp]);
+        }
+    }
+
+    DXImage::new_2d(metadata.format, width, height, layout.slices, 1, &buffer)
+}
+
+fn bytes_per_pixel(image: &DXImage, width: usize, height: usize) -> Result<usize> {
+    let metadata = image.metadata()?;
+    if metadata.format.is_compressed() {
+        return error_message("Internal error: atlases are built from uncompressed images");
+    }
+
+    Ok(image.image_len(0)? / (width * height))
+}
+
+#[test]
+fn test_atlas_layout() {
+    let layout = AtlasLayout::new(4, 6);
+    assert_eq!((layout.columns, layout.rows), (4, 2));
+    assert_eq!(layout.slice_size(256, 128).unwrap(), (64, 64));
+    assert!(layout.slice_size(250, 128).is_err());
+
+    assert_eq!(AtlasLayout::new(0, 3).columns, 1);
+    assert_eq!(AtlasLayout::new(10, 3).columns, 3);
+
+    assert_eq!(
+        atlas_file(Utf8Path::new("dir/leaves_c#01.tga"), ImageFormat::Tga),
+        Utf8PathBuf::from("dir/leaves_c.tga")
+    );
+}
@@ -11,7 +11,9 @@ use eframe::egui::{
     vec2,
     Button,
     CentralPanel,
+    ComboBox,
     Context,
+    DragValue,
     Event,
     Label,
     Layout,
@@ -31,6 +33,7 @@ use eframe::epaint::{Color32, FontId};
 use eframe::{App, Frame};
 use image::{DynamicImage, ImageFormat};
 use parking_lot::Mutex;
+use spidertexlib::channels::ChannelRecipe;
 use spidertexlib::convert::{convert, TaskResult};
 use spidertexlib::files::{
     is_image_ext,
@@ -46,13 +49,31 @@ use spidertexlib::files::{
     Scanned,
 };
 use spidertexlib::images::{DxImport, Image, ImageRs};
+use spidertexlib::naming;
+use spidertexlib::options::{
+    AlphaMode,
+    AspectMode,
+    ColorOptions,
+    ColorSpace,
+    CompressionQuality,
+    CubeLayout,
+    ExportFormat,
+    ExportOptions,
+    ImportOptions,
+    NormalMapMode,
+    NormalMapOptions,
+    Options,
+    ResizeFilter,
+    ToneMapOperator,
+    ToneMapOptions,
+};
 use spidertexlib::prelude::*;
 
 use super::{theme, widgets, AppWindow};
 use crate::inputs::{Action, Inputs};
 use crate::log;
 
-pub fn batch(inputs: Inputs) {
+pub fn batch(inputs: Inputs, options: Options) {
     let action = inputs.default_action();
     let progress_rx = scan_thread(inputs);
 
@@ -63,6 +84,7 @@ pub fn batch(inputs: Inputs) {
         progress: 0.0,
         progress_rx,
         action_changed: true,
+        options,
     };
 
     batch.run();
@@ -76,6 +98,7 @@ enum Batch {
         progress:       f32,
         progress_rx:    Receiver<(f32, FileGroup<Scanned>)>,
         action_changed: bool,
+        options:        Options,
     },
     Running {
         num_tasks:  usize,
@@ -107,6 +130,14 @@ impl Batch {
         }
     }
 
+    fn options_mut(&mut self) -> Option<&mut Options> {
+        if let Self::Setup { options, .. } = self {
+            Some(options)
+        } else {
+            None
+        }
+    }
+
     const fn action(&self) -> Action {
         if let Self::Setup { action, .. } = self {
             *action
@@ -169,6 +200,7 @@ impl Batch {
             action,
             imports,
             exports,
+            options,
             ..
         } = std::mem::replace(self, new)
         {
@@ -184,13 +216,12 @@ impl Batch {
                     .log_failure()
                     .ignore();
 
-                for mut group in groups {
-                    todo!()
-                    // if let Some(result) = convert(group) {
-                    //     tx.send(result)
-                    //         .log_failure_as("Reporting completed task")
-                    //         .ignore();
-                    // }
+                for group in groups {
+                    if let Some(result) = convert(group, &options) {
+                        tx.send(result)
+                            .log_failure_as("Reporting completed task")
+                            .ignore();
+                    }
                 }
 
                 let duration = start.elapsed();
@@ -205,35 +236,37 @@ impl Batch {
     }
 
     fn status_screen(&mut self, ui: &mut Ui) {
-        // if let Self::Running { results, .. } | Self::Complete { results } = self {
-        //     ScrollArea::vertical().stick_to_bottom(true).show(ui, |ui| {
-        //         for result in results {
-        //             match result {
-        //                 TaskResult::Started(_) => (),
-        //                 TaskResult::Result(group, duration) => {
-        //                     widgets::file_name_labels(
-        //                         ui,
-        //                         &group.inputs,
-        //                         theme::text::normal,
-        //                         theme::text::normal,
-        //                     );
-        //                     ui.indent("Outputs", |ui| {
-        //                         if print_outputs(ui, &group.status) {
-        //                             ui.label(theme::text::good(format!(
-        //                                 "Processed in {duration:?}"
-        //                             )));
-        //                         } else {
-        //                             ui.label(theme::text::error(format!("Failed in {duration:?}")));
-        //                         }
-        //                     });
-        //                 }
-        //                 TaskResult::Complete(duration) => {
-        //                     ui.label(theme::text::good(format!("Completed in {duration:?}")));
-        //                 }
-        //             }
-        //         }
-        //     });
-        // }
+        if let Self::Running { results, .. } | Self::Complete { results } = self {
+            ScrollArea::vertical().stick_to_bottom(true).show(ui, |ui| {
+                for result in results.iter() {
+                    match result {
+                        TaskResult::Started(_) => (),
+                        TaskResult::Result(group, status, duration) => {
+                            if let FileStatus::Ok(_, inputs) = &group.input {
+                                widgets::file_name_labels(
+                                    ui,
+                                    inputs,
+                                    theme::text::normal,
+                                    theme::text::normal,
+                                );
+                            }
+                            ui.indent("Outputs", |ui| {
+                                if print_outputs(ui, status) {
+                                    ui.label(theme::text::good(format!(
+                                        "Processed in {duration:?}"
+                                    )));
+                                } else {
+                                    ui.label(theme::text::error(format!("Failed in {duration:?}")));
+                                }
+                            });
+                        }
+                        TaskResult::Complete(duration) => {
+                            ui.label(theme::text::good(format!("Completed in {duration:?}")));
+                        }
+                    }
+                }
+            });
+        }
     }
 
     fn finish(&mut self) {
@@ -260,7 +293,7 @@ impl AppWindow for Batch {
 
                     match &group.file_type() {
                         Some(FileType::Texture) => exports.push(group),
-                        Some(FileType::Image(_)) => imports.push(group),
+                        Some(FileType::Image(_) | FileType::Lut) => imports.push(group),
                         None => (),
                     }
 
@@ -303,6 +336,37 @@ impl AppWindow for Batch {
                     self.set_action(Action::Export);
                 }
             });
+            if let Some(options) = self.options_mut() {
+                if action == Action::Import {
+                    ui.group(|ui| {
+                        ui.set_width(ui.available_width());
+                        import_options(ui, &mut options.import);
+                    });
+                } else {
+                    ui.group(|ui| {
+                        ui.set_width(ui.available_width());
+                        export_options(ui, &mut options.export);
+                    });
+                    ui.group(|ui| {
+                        ui.set_width(ui.available_width());
+                        tonemap_options(ui, &mut options.tonemap);
+                    });
+                }
+                ui.group(|ui| {
+                    ui.set_width(ui.available_width());
+                    color_options(ui, &mut options.color);
+                });
+                ui.group(|ui| {
+                    ui.set_width(ui.available_width());
+                    normal_map_options(ui, &mut options.normals);
+                });
+                ui.group(|ui| {
+                    ui.set_width(ui.available_width());
+                    cubemap_options(ui, &mut options.cubemap);
+                    ui.separator();
+                    atlas_options(ui, &mut options.atlas);
+                });
+            }
             ui.group(|ui| {
                 ui.label(
                     "The list to the right shows the names of files to be processed, followed by \
@@ -360,6 +424,202 @@ impl AppWindow for Batch {
     fn can_close(&mut self) -> bool { !self.in_progress() }
 }
 
+fn import_options(ui: &mut Ui, options: &mut ImportOptions) {
+    ui.label("Resize filter");
+    ComboBox::from_id_source("resize_filter")
+        .selected_text(options.resize_filter.to_string())
+        .show_ui(ui, |ui| {
+            for filter in ResizeFilter::ALL {
+                ui.selectable_value(&mut options.resize_filter, filter, filter.to_string());
+            }
+        });
+
+    if CompressionQuality::SUPPORTED {
+        ui.label("Compression quality");
+        ComboBox::from_id_source("compression_quality")
+            .selected_text(options.quality.to_string())
+            .show_ui(ui, |ui| {
+                for quality in CompressionQuality::ALL {
+                    ui.selectable_value(&mut options.quality, quality, quality.to_string());
+                }
+            });
+    }
+    ui.checkbox(&mut options.report_error, "Report the compression error")
+        .on_hover_text("Textures that lose a lot of detail are always reported");
+    ui.checkbox(&mut options.keep_resolution, "Keep the image resolution")
+        .on_hover_text("Rewrites the texture header instead of resizing the images");
+
+    ui.separator();
+    ui.label("Wrong aspect ratio");
+    let aspect = &mut options.aspect;
+    if ui.radio(*aspect == AspectMode::Stretch, "Stretch").clicked() {
+        *aspect = AspectMode::Stretch;
+    }
+    if ui.radio(*aspect == AspectMode::Crop, "Crop").clicked() {
+        *aspect = AspectMode::Crop;
+    }
+    ui.horizontal(|ui| {
+        let padding = matches!(aspect, AspectMode::Pad(_));
+        if ui.radio(padding, "Pad").clicked() && !padding {
+            *aspect = AspectMode::Pad(AspectMode::DEFAULT_PAD);
+        }
+        if let AspectMode::Pad(color) = aspect {
+            ui.color_edit_button_srgba_unmultiplied(color);
+        }
+    });
+    if ui.radio(*aspect == AspectMode::Reject, "Skip the image").clicked() {
+        *aspect = AspectMode::Reject;
+    }
+
+    ui.separator();
+    let mut packing = options.pack.is_some();
+    if ui
+        .checkbox(&mut packing, "Pack channels from greyscale images")
+        .on_hover_text("Reads name_r.png, name_g.png, name_b.png and name_a.png for name.png")
+        .changed()
+    {
+        options.pack = packing.then(ChannelRecipe::split);
+    }
+}
+
+fn color_options(ui: &mut Ui, options: &mut ColorOptions) {
+    ui.label("Image colour space");
+    ComboBox::from_id_source("color_space")
+        .selected_text(options.space.to_string())
+        .show_ui(ui, |ui| {
+            for space in ColorSpace::ALL {
+                ui.selectable_value(&mut options.space, space, space.to_string());
+            }
+        })
+        .response
+        .on_hover_text("Auto keeps the values of the texture format");
+
+    ui.label("Alpha");
+    ComboBox::from_id_source("alpha_mode")
+        .selected_text(options.alpha.to_string())
+        .show_ui(ui, |ui| {
+            for mode in AlphaMode::ALL {
+                ui.selectable_value(&mut options.alpha, mode, mode.to_string());
+            }
+        });
+}
+
+fn cubemap_options(ui: &mut Ui, layout: &mut CubeLayout) {
+    ui.label("Cubemap layout");
+    ComboBox::from_id_source("cubemap_layout")
+        .selected_text(layout.to_string())
+        .show_ui(ui, |ui| {
+            for option in CubeLayout::ALL {
+                ui.selectable_value(layout, option, option.to_string());
+            }
+        });
+}
+
+fn tonemap_options(ui: &mut Ui, options: &mut ToneMapOptions) {
+    ui.checkbox(&mut options.preview, "Save a PNG preview of HDR images")
+        .on_hover_text("Written as name.preview.png, which is never imported");
+    ui.label("Tone mapping");
+    ComboBox::from_id_source("tonemap_operator")
+        .selected_text(options.operator.to_string())
+        .show_ui(ui, |ui| {
+            for operator in ToneMapOperator::ALL {
+                ui.selectable_value(&mut options.operator, operator, operator.to_string());
+            }
+        });
+    ui.horizontal(|ui| {
+        ui.label("Exposure");
+        ui.add(
+            DragValue::new(&mut options.exposure)
+                .speed(0.1)
+                .clamp_range(-10.0 ..= 10.0)
+                .suffix(" EV"),
+        );
+    });
+}
+
+fn export_options(ui: &mut Ui, options: &mut ExportOptions) {
+    ui.label("Image format");
+    ComboBox::from_id_source("export_image_format")
+        .selected_text(options.image_format.to_string())
+        .show_ui(ui, |ui| {
+            for image_format in ExportFormat::ALL {
+                ui.selectable_value(
+                    &mut options.image_format,
+                    image_format,
+                    image_format.to_string(),
+                );
+            }
+        })
+        .response
+        .on_hover_text("HDR textures are saved as EXR unless DDS is picked");
+
+    ui.label("Output directory");
+    let mut directory = options
+        .directory
+        .as_ref()
+        .map_or_else(String::new, ToString::to_string);
+    if ui
+        .text_edit_singleline(&mut directory)
+        .on_hover_text("Empty saves the images next to the textures")
+        .changed()
+    {
+        options.directory = (!directory.is_empty()).then(|| Utf8PathBuf::from(directory));
+    }
+
+    ui.label("File names");
+    let mut template = options.name_template.clone().unwrap_or_default();
+    if ui
+        .text_edit_singleline(&mut template)
+        .on_hover_text(format!(
+            "Placeholders: {{{}}}. Empty uses {}",
+            naming::PLACEHOLDERS.join("}, {"),
+            naming::DEFAULT_TEMPLATE
+        ))
+        .changed()
+    {
+        options.name_template = (!template.is_empty()).then_some(template);
+    }
+    if let Some(Err(error)) = options.name_template.as_deref().map(naming::check_template) {
+        ui.label(theme::text::error(error.to_string()));
+    }
+
+    ui.separator();
+    ui.checkbox(&mut options.split_channels, "Also save each channel as a greyscale image");
+    ui.checkbox(&mut options.mip_levels, "Also save every mip level");
+    ui.checkbox(&mut options.dds_chain, "Save a DDS file with the whole mip chain instead")
+        .on_hover_text("The texture data as it is, without the colour options");
+}
+
+fn atlas_options(ui: &mut Ui, atlas: &mut Option<usize>) {
+    let mut enabled = atlas.is_some();
+    if ui
+        .checkbox(&mut enabled, "Arrays as one atlas image")
+        .on_hover_text("Slices are placed in a grid instead of name#01, name#02... files")
+        .changed()
+    {
+        *atlas = enabled.then_some(4);
+    }
+    if let Some(columns) = atlas {
+        ui.horizontal(|ui| {
+            ui.label("Columns");
+            ui.add(DragValue::new(columns).clamp_range(1 ..= 64));
+        });
+    }
+}
+
+fn normal_map_options(ui: &mut Ui, options: &mut NormalMapOptions) {
+    ui.label("Normal maps");
+    ComboBox::from_id_source("normal_map_mode")
+        .selected_text(options.mode.to_string())
+        .show_ui(ui, |ui| {
+            for mode in NormalMapMode::ALL {
+                ui.selectable_value(&mut options.mode, mode, mode.to_string());
+            }
+        });
+    ui.checkbox(&mut options.flip_green, "Flip green (OpenGL/DirectX)");
+    ui.checkbox(&mut options.reconstruct_z, "Reconstruct Z on export");
+}
+
 fn scan_thread(inputs: Inputs) -> Receiver<(f32, FileGroup<Scanned>)> {
     let (tx, rx) = std::sync::mpsc::channel();
     let total = inputs.len();
@@ -406,8 +666,15 @@ where G: FileGroupInfo {
                     theme::text::warning(tooltip)
                 });
             }
-            if let OutputFormat::Exact { format, .. } = output_format {
-                ui.label(theme::text::normal(format.to_string()));
+            if let OutputFormat::Exact {
+                format, provenance, ..
+            } = output_format
+            {
+                ui.label(theme::text::normal(format!(
+                    "{format} ({})",
+                    provenance.confidence
+                )))
+                .on_hover_text(theme::text::highlight(provenance.to_string()));
             }
         }
         FileStatus::Error(error) => {
@@ -435,12 +702,14 @@ where G: FileGroupInfo {
         }
         FileStatus::Error(error) => {
             let mut tooltip = String::new();
-            if let OutputFormat::Candidates(formats) = output_format {
+            if let OutputFormat::Candidates(formats, provenance) = output_format {
                 for format in formats.iter() {
                     tooltip.push_str(&format.to_string());
                     tooltip.push('\n');
                 }
                 tooltip.push('\n');
+                tooltip.push_str(&provenance.to_string());
+                tooltip.push_str("\n\n");
             }
             tooltip.push_str("This file must be manually converted.");
             ui.label(theme::text::error(error.to_string()))
@@ -450,30 +719,26 @@ where G: FileGroupInfo {
 }
 
 fn print_outputs(ui: &mut Ui, status: &FileStatus) -> bool {
-    // let is_successful = match status {
-    //     FileStatus::Error(error) => {
-    //         ui.label(theme::text::error(error));
-    //         false
-    //     }
-    //     FileStatus::Unknown | FileStatus::Ok(None) | FileStatus::Warnings(None,
-    // _) => false,     FileStatus::Ok(Some(outputs)) => {
-    //         widgets::file_name_labels(ui, outputs, theme::text::good,
-    // theme::text::normal);         true
-    //     }
-    //     FileStatus::Warnings(Some(outputs), warnings) => {
-    //         widgets::file_name_labels(ui, outputs, theme::text::warning, |mut
-    // text| {             text.push_str("\n\n");
-    //             for warning in warnings {
-    //                 text.push_str(warning);
-    //                 text.push('\n');
-    //             }
-    //             theme::text::warning(text)
-    //         });
-    //         true
-    //     }
-    // };
-    let is_successful = true;
-
-    #[allow(clippy::let_and_return)]
-    is_successful
+    match status {
+        FileStatus::Error(error) => {
+            ui.label(theme::text::error(error));
+            false
+        }
+        FileStatus::Unknown => false,
+        FileStatus::Ok(warnings, outputs) => {
+            if warnings.is_empty() {
+                widgets::file_name_labels(ui, outputs, theme::text::good, theme::text::normal);
+            } else {
+                widgets::file_name_labels(ui, outputs, theme::text::warning, |mut text| {
+                    text.push_str("\n\n");
+                    for warning in warnings.iter() {
+                        text.push_str(warning);
+                        text.push('\n');
+                    }
+                    theme::text::warning(text)
+                });
+            }
+            true
+        }
+    }
 }
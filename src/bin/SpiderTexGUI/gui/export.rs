@@ -20,13 +20,18 @@ use eframe::emath::Align;
 use eframe::{App, Frame};
 use image::DynamicImage;
 use spidertexlib::formats::{probe_textures_2, TextureFormat};
+use spidertexlib::options::Options;
 use spidertexlib::prelude::*;
 
 use super::preview::Preview;
 use super::{theme, widgets};
 use crate::log;
 
-pub fn export_ui(export_files: Vec<Utf8PathBuf>, common_name: String) -> Result<()> {
+pub fn export_ui(
+    export_files: Vec<Utf8PathBuf>,
+    common_name: String,
+    run_options: &Options,
+) -> Result<()> {
     let mut registry = Registry::load()?;
 
     let (detected_formats, smallest_file, image_buffer) =
@@ -58,6 +63,7 @@ pub fn export_ui(export_files: Vec<Utf8PathBuf>, common_name: String) -> Result<
         .expect("No parent folder")
         .join(common_name);
 
+    let tonemap = run_options.tonemap.clone();
     let selections = ExportSelections {
         export_files,
         image_buffer,
@@ -76,10 +82,16 @@ pub fn export_ui(export_files: Vec<Utf8PathBuf>, common_name: String) -> Result<
 
             log::set_ui_context(&cc.egui_ctx);
 
-            let preview = Preview::from_buffer_and_format(
+            let candidates = if selections.detected_formats.is_empty() {
+                std::slice::from_ref(&selections.selected_format)
+            } else {
+                &selections.detected_formats
+            };
+            let preview = Preview::from_buffer_and_formats(
                 &cc.egui_ctx,
                 &selections.image_buffer,
-                &selections.selected_format,
+                candidates,
+                &tonemap,
             );
 
             let state = ExportState::Preview(Some(Box::new(selections)));
@@ -12,8 +12,11 @@ use eframe::egui::{
 };
 use eframe::epaint::{vec2, Vec2};
 use image::DynamicImage;
-use spidertexlib::formats::{guess_dimensions, ColorPlanes, TextureFormat};
+use spidertexlib::dxtex::DXImage;
+use spidertexlib::formats::{guess_dimensions, ColorPlanes, DimensionMatch, TextureFormat};
+use spidertexlib::options::ToneMapOptions;
 use spidertexlib::prelude::*;
+use spidertexlib::tonemap;
 use spidertexlib::util::into_n_slices;
 
 use super::theme;
@@ -51,20 +54,38 @@ fn placeholder(ctx: &Context) -> TextureHandle {
 
 fn compressed_to_texturehandles(
     ctx: &Context,
-    format: &TextureFormat,
-    mut data: &[u8],
+    formats: &[TextureFormat],
+    data: &[u8],
+    tonemap: &ToneMapOptions,
 ) -> Option<Vec<TextureHandle>> {
-    if !matches!(format.planes(), ColorPlanes::Rgba) {
+    guess_dimensions(data.len(), formats)
+        .into_iter()
         // FIXME
-        return None;
-    }
+        .filter(|found| matches!(found.format.planes(), ColorPlanes::Rgba | ColorPlanes::Hdr))
+        .find_map(|found| match_to_texturehandles(ctx, found, data, tonemap))
+}
+
+fn match_to_texturehandles(
+    ctx: &Context,
+    found: DimensionMatch<'_>,
+    mut data: &[u8],
+    tonemap: &ToneMapOptions,
+) -> Option<Vec<TextureHandle>> {
+    let DimensionMatch {
+        format,
+        dimensions,
+        strip_header,
+        ..
+    } = found;
 
-    // FIXME: handle multiple formats
-    let (dimensions, strip_header) = guess_dimensions(data.len(), &[format.clone()])?;
     if strip_header {
         data = &data[TEXTURE_HEADER_SIZE ..];
     }
 
+    if format.planes() == ColorPlanes::Hdr {
+        return hdr_to_texturehandles(ctx, found, data, tonemap).log_failure().ok();
+    }
+
     spidertexlib::dxtex::decompress_texture(
         format.dxgi_format,
         dimensions.width,
@@ -82,6 +103,29 @@ fn compressed_to_texturehandles(
     })
 }
 
+/// Tone maps every array slice with the operator and exposure of the run.
+fn hdr_to_texturehandles(
+    ctx: &Context,
+    found: DimensionMatch<'_>,
+    data: &[u8],
+    tonemap: &ToneMapOptions,
+) -> Result<Vec<TextureHandle>> {
+    let DimensionMatch {
+        format, dimensions, ..
+    } = found;
+
+    let image = DXImage::with_dimensions(format.dxgi_format, dimensions, format.array_size, data)?;
+    let slices = (0 .. format.array_size)
+        .map(|slice| tonemap::to_ldr(&image, slice, tonemap)?.image(0))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(rgba_to_texturehandles(
+        ctx,
+        [dimensions.width, dimensions.height],
+        slices.iter().map(Vec::as_slice),
+    ))
+}
+
 fn rgba_to_texturehandles<'a>(
     ctx: &Context,
     dimensions: [usize; 2],
@@ -112,9 +156,14 @@ impl Preview {
         }
     }
 
-    pub fn from_buffer_and_format(ctx: &Context, data: &[u8], format: &TextureFormat) -> Self {
+    pub fn from_buffer_and_formats(
+        ctx: &Context,
+        data: &[u8],
+        formats: &[TextureFormat],
+        tonemap: &ToneMapOptions,
+    ) -> Self {
         let placeholder = placeholder(ctx);
-        let images = compressed_to_texturehandles(ctx, format, data).unwrap_or_default();
+        let images = compressed_to_texturehandles(ctx, formats, data, tonemap).unwrap_or_default();
 
         Self {
             images,
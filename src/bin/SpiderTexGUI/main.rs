@@ -1,7 +1,5 @@
 //! TODO: Catch thread panics
-//! TODO: Is it srgb? is it premultiplied alpha?
 //! TODO: Make the debug mode database a lazy static
-//! TODO: LUT, search for day_01_lut
 //! TODO: rename expected_highres_buffer_size etc
 //! TODO: window icon
 //! TODO: PNG metadata: WIC gets it wrong, image_rs needs to read the entire
@@ -41,21 +39,27 @@ fn run() -> Result<()> {
 
     registry::load()?;
 
-    let inputs = inputs::gather_from_args();
+    let (options, inputs) = inputs::gather_from_args()?;
     let job = inputs::make_job(inputs);
     event!(DEBUG, ?job);
 
     match job {
-        Job::Import(group) => gui::batch(Inputs {
-            textures: vec![],
-            images:   vec![group.0],
-        }),
-        Job::Export(group) => gui::batch(Inputs {
-            textures: vec![group.0],
-            images:   vec![],
-        }),
+        Job::Import(group) => gui::batch(
+            Inputs {
+                textures: vec![],
+                images:   vec![group.0],
+            },
+            options,
+        ),
+        Job::Export(group) => gui::batch(
+            Inputs {
+                textures: vec![group.0],
+                images:   vec![],
+            },
+            options,
+        ),
         Job::Batch(inputs) => {
-            gui::batch(inputs);
+            gui::batch(inputs, options);
         }
         Job::Nothing => return error_message("No valid files were specified."),
     }
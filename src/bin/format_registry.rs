@@ -0,0 +1,240 @@
+//! Command line access to the texture format registry.
+//!
+//! ```text
+//! format_registry query [FILTERS] [--json]
+//!     --format <name or number>   DXGI format, e.g. BC7 or 98
+//!     --width <n>                 standard or high-resolution width
+//!     --height <n>                standard or high-resolution height
+//!     --array <n>                 array size
+//!     --highres <yes|no>          whether the format has a high-resolution texture
+//!     --mips <n>                  standard or high-resolution mip count
+//!     --size <n>                  data size in bytes, with or without the header
+//!     --example <text>            part of the example file name
+//!
+//! format_registry check
+//!     Validates data sizes, raw headers, the length index and id collisions
+//!
+//! format_registry export <output> [SELECTION]
+//!     --id <hex>                  format id, may be repeated
+//!     --size <n>                  file size in bytes, may be repeated
+//!     --pattern <regex>           example file name pattern, may be repeated
+//!
+//! format_registry scan <dir> [OPTIONS]
+//!     --output <file>             write new and changed formats as a user registry
+//!     --diff <file>               write the differences to the loaded registry as JSON
+//!     --overrides <file>          file name overrides to detect with and include in the output
+//! ```
+
+use camino::{Utf8Path, Utf8PathBuf};
+use spidertexlib::formats::parse_dxgi_format;
+use spidertexlib::prelude::*;
+use spidertexlib::registry::{results_to_json, results_to_table, FormatQuery, FormatSelection};
+
+const USAGE: &str = "Usage: format_registry query [--format F] [--width N] [--height N] [--array \
+                     N] [--highres yes|no] [--mips N] [--size N] [--example TEXT] [--json]
+       format_registry check
+       format_registry export <output> [--id HEX]... [--size N]... [--pattern REGEX]...
+       format_registry scan <dir> [--output FILE] [--diff FILE] [--overrides FILE]";
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+
+    let result = match args.next().as_deref() {
+        Some("query") => query(args),
+        Some("check") => check(),
+        Some("export") => export(args),
+        Some("scan") => scan(args),
+        _ => error_message(USAGE),
+    };
+
+    if let Err(error) = result {
+        eprintln!("{error}");
+        std::process::exit(1);
+    }
+}
+
+fn query(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let mut query = FormatQuery::default();
+    let mut json = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--format" => {
+                let value = value_for(&arg, &mut args)?;
+                query.dxgi_format = Some(
+                    parse_dxgi_format(&value)
+                        .ok_or_else(|| Error::message(format!("Unknown format {value}")))?,
+                );
+            }
+            "--width" => query.width = Some(parse_number(&arg, &mut args)?),
+            "--height" => query.height = Some(parse_number(&arg, &mut args)?),
+            "--array" => query.array_size = Some(parse_number(&arg, &mut args)?),
+            "--mips" => query.mipmaps = Some(parse_number(&arg, &mut args)?),
+            "--size" => query.data_size = Some(parse_number(&arg, &mut args)?),
+            "--example" => query.example = Some(value_for(&arg, &mut args)?),
+            "--highres" => {
+                query.has_highres = Some(match value_for(&arg, &mut args)?.as_str() {
+                    "yes" | "true" => true,
+                    "no" | "false" => false,
+                    other => return error_message(format!("Expected yes or no, got {other}")),
+                });
+            }
+            other => return error_message(format!("Unknown option {other}\n{USAGE}")),
+        }
+    }
+
+    registry::load()?;
+    let results = registry().query(&query);
+
+    if json {
+        println!("{}", results_to_json(&results)?);
+    } else {
+        print!("{}", results_to_table(&results));
+        println!("{} formats found", results.len());
+    }
+
+    Ok(())
+}
+
+fn check() -> Result<()> {
+    registry::load()?;
+    let registry = registry();
+
+    let collisions = registry.collisions();
+    let issues = registry.check_integrity();
+
+    for collision in collisions {
+        println!("{collision}");
+    }
+    for issue in &issues {
+        println!("{issue}");
+    }
+
+    let problems = collisions.len() + issues.len();
+    if problems == 0 {
+        println!("{} formats checked, no problems found", registry.formats.len());
+        Ok(())
+    } else {
+        error_message(format!("{problems} problems found"))
+    }
+}
+
+fn export(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let output = args
+        .next()
+        .filter(|arg| !arg.starts_with("--"))
+        .ok_or_else(|| Error::message(USAGE))?;
+    let mut selection = FormatSelection::default();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--id" => selection
+                .ids
+                .push(FormatId::from_hex(&value_for(&arg, &mut args)?)?),
+            "--size" => selection.sizes.push(parse_number(&arg, &mut args)?),
+            "--pattern" => selection.add_pattern(&value_for(&arg, &mut args)?)?,
+            other => return error_message(format!("Unknown option {other}\n{USAGE}")),
+        }
+    }
+
+    if selection.is_empty() {
+        return error_message("Nothing selected, use --id, --size or --pattern");
+    }
+
+    registry::load()?;
+    let subset = registry().subset(&selection);
+    if subset.formats.is_empty() {
+        return error_message("No formats matched the selection");
+    }
+
+    subset.save_as(&output)?;
+    println!("Saved {} formats to {output}", subset.formats.len());
+
+    Ok(())
+}
+
+fn scan(mut args: impl Iterator<Item = String>) -> Result<()> {
+    let dir = args
+        .next()
+        .filter(|arg| !arg.starts_with("--"))
+        .map(Utf8PathBuf::from)
+        .ok_or_else(|| Error::message(USAGE))?;
+    let mut output = None;
+    let mut diff = None;
+    let mut overrides = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--output" => output = Some(value_for(&arg, &mut args)?),
+            "--diff" => diff = Some(value_for(&arg, &mut args)?),
+            "--overrides" => overrides = Some(value_for(&arg, &mut args)?),
+            other => return error_message(format!("Unknown option {other}\n{USAGE}")),
+        }
+    }
+
+    if !dir.is_dir() {
+        return error_message(format!("{dir} is not a directory"));
+    }
+
+    registry::load()?;
+    let registry = registry();
+    let report = registry.scan(&dir, overrides.as_deref().map(Utf8Path::new))?;
+
+    for id in &report.new {
+        println!("New      {id} {}", report.learned.get(id));
+    }
+    for id in &report.changed {
+        println!(
+            "Changed  {id} {} -> {}",
+            registry.get(id),
+            report.learned.get(id)
+        );
+    }
+    for conflict in &report.conflicts {
+        println!("Conflict {conflict}");
+    }
+    for file in &report.unknown {
+        println!("Unknown  {file}");
+    }
+    for (file, error) in &report.errors {
+        println!("Error    {file}: {error}");
+    }
+    println!(
+        "{} files scanned: {} new, {} changed, {} conflicting, {} unknown, {} failed",
+        report.scanned,
+        report.new.len(),
+        report.changed.len(),
+        report.conflicts.len(),
+        report.unknown.len(),
+        report.errors.len()
+    );
+
+    if let Some(output) = output {
+        let updates = report.updates();
+        updates.save_as(&output)?;
+        println!("Saved {} formats to {output}", updates.formats.len());
+    }
+    if let Some(diff) = diff {
+        let json = serde_json::to_string_pretty(&report.diff(registry))?;
+        std::fs::write(&diff, json)?;
+        println!("Saved diff to {diff}");
+    }
+
+    Ok(())
+}
+
+fn value_for(option: &str, args: &mut impl Iterator<Item = String>) -> Result<String> {
+    args.next()
+        .ok_or_else(|| Error::message(format!("{option} needs a value")))
+}
+
+fn parse_number<T: std::str::FromStr>(
+    option: &str,
+    args: &mut impl Iterator<Item = String>,
+) -> Result<T> {
+    let value = value_for(option, args)?;
+    value
+        .parse()
+        .map_err(|_| Error::message(format!("{option} expects a number, got {value}")))
+}
@@ -0,0 +1,319 @@
+//! Channel packing: building one texture from channels of several images, and
+//! splitting an exported image back into one greyscale file per channel.
+//!
+//! A recipe names a source for each of the red, green, blue and alpha
+//! channels, e.g. `r={name}_gloss.png,g={name}_ao.png,b=0,a=255`. `{name}` is
+//! the imported file's name without its extension and relative files are
+//! looked up next to it. A source reads the red channel of its image unless
+//! another one is given with `:g`, `:b` or `:a`.
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+use windows::Win32::Graphics::Dxgi::Common::{
+    DXGI_FORMAT_R8G8B8A8_UNORM,
+    DXGI_FORMAT_R8G8B8A8_UNORM_SRGB,
+    DXGI_FORMAT_R8_UNORM,
+};
+
+use crate::dxtex::DXImage;
+use crate::formats::ImageFormat;
+use crate::prelude::*;
+
+pub const NAME_PLACEHOLDER: &str = "{name}";
+/// Recipe that reads back the files written by [`split`]
+pub const SPLIT_RECIPE: &str = "split";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Channel {
+    R,
+    G,
+    B,
+    A,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChannelSource {
+    Image { file: String, channel: Channel },
+    Constant(u8),
+}
+
+/// Where each of the red, green, blue and alpha channels comes from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelRecipe {
+    pub channels: [ChannelSource; 4],
+}
+
+impl Channel {
+    pub const ALL: [Self; 4] = [Self::R, Self::G, Self::B, Self::A];
+
+    #[must_use]
+    pub const fn index(self) -> usize {
+        match self {
+            Self::R => 0,
+            Self::G => 1,
+            Self::B => 2,
+            Self::A => 3,
+        }
+    }
+}
+
+impl ChannelRecipe {
+    /// Packs the files written by [`split`] for the same image.
+    #[must_use]
+    pub fn split() -> Self {
+        Self {
+            channels: Channel::ALL.map(|channel| ChannelSource::Image {
+                file: format!("{NAME_PLACEHOLDER}_{channel}.png"),
+                channel: Channel::R,
+            }),
+        }
+    }
+
+    /// The source file a recipe entry refers to, for the imported `image_file`.
+    #[must_use]
+    pub fn source_file(file: &str, image_file: &Utf8Path) -> Utf8PathBuf {
+        let file = file.replace(NAME_PLACEHOLDER, image_file.file_stem().unwrap_or_default());
+        image_file
+            .parent()
+            .map_or_else(|| Utf8PathBuf::from(&file), |dir| dir.join(&file))
+    }
+
+    /// Builds an `R8G8B8A8_UNORM` image for `image_file` from the recipe. All
+    /// sources must have the same size, which is also the size of
+    /// `image_file` when the recipe only has constants.
+    pub fn pack(&self, image_file: &Utf8Path) -> Result<DXImage> {
+        let mut sources: Vec<(&str, Vec<u8>)> = Vec::new();
+        let mut size = None;
+
+        for source in &self.channels {
+            let file = match source {
+                ChannelSource::Image { file, .. } => file.as_str(),
+                ChannelSource::Constant(_) => continue,
+            };
+            if sources.iter().any(|(loaded, _)| *loaded == file) {
+                continue;
+            }
+
+            let path = Self::source_file(file, image_file);
+            let image = DXImage::load(&path).map_err(|error| {
+                Error::message(format!("Failed to load channel source {path}: {error}"))
+            })?;
+            let metadata = image.metadata()?;
+            match size {
+                None => size = Some((metadata.width, metadata.height)),
+                Some(size) if size != (metadata.width, metadata.height) => {
+                    return error_message(format!(
+                        "Channel source {path} is {}x{}, the others are {}x{}",
+                        metadata.width, metadata.height, size.0, size.1
+                    ));
+                }
+                Some(_) => {}
+            }
+
+            let rgba = image.to_format(DXGI_FORMAT_R8G8B8A8_UNORM)?;
+            sources.push((file, rgba.image(0)?));
+        }
+
+        let (width, height) = match size {
+            Some(size) => size,
+            None => {
+                let metadata = dxtex::metadata(image_file)?;
+                (metadata.width, metadata.height)
+            }
+        };
+
+        let mut buffer = vec![0_u8; width * height * 4];
+        for (index, source) in self.channels.iter().enumerate() {
+            match source {
+                ChannelSource::Constant(value) => {
+                    buffer.chunks_exact_mut(4).for_each(|pixel| pixel[index] = *value);
+                }
+                ChannelSource::Image { file, channel } => {
+                    let data = sources
+                        .iter()
+                        .find_map(|(loaded, data)| (loaded == file).then_some(data))
+                        .ok_or_else(|| {
+                            Error::message("Internal error: channel source not loaded")
+                        })?;
+                    for (pixel, source) in buffer.chunks_exact_mut(4).zip(data.chunks_exact(4)) {
+                        pixel[index] = source[channel.index()];
+                    }
+                }
+            }
+        }
+
+        DXImage::new_2d(DXGI_FORMAT_R8G8B8A8_UNORM, width, height, 1, 1, &buffer)
+    }
+}
+
+/// The greyscale file [`split`] writes for one channel of `output_file`.
+#[must_use]
+pub fn split_file(output_file: &Utf8Path, channel: Channel) -> Utf8PathBuf {
+    output_file.with_file_name(format!(
+        "{}_{channel}.png",
+        output_file.file_stem().unwrap_or_default()
+    ))
+}
+
+/// Writes every channel of one array slice of `image` as a greyscale PNG file
+/// next to `output_file`. Missing channels are written too, so that
+/// [`ChannelRecipe::split`] always finds its files.
+pub fn split(
+    image: &DXImage,
+    array_index: usize,
+    output_file: &Utf8Path,
+) -> Result<Vec<Utf8PathBuf>> {
+    let metadata = image.metadata()?;
+    // Keep the stored values, masks shouldn't go through the sRGB curve
+    let rgba = image.to_format(if metadata.format.is_srgb() {
+        DXGI_FORMAT_R8G8B8A8_UNORM_SRGB
+    } else {
+        DXGI_FORMAT_R8G8B8A8_UNORM
+    })?;
+    let pixels = rgba.image(array_index * metadata.mipLevels)?;
+
+    Channel::ALL
+        .into_iter()
+        .map(|channel| {
+            let grey: Vec<u8> = pixels
+                .chunks_exact(4)
+                .map(|pixel| pixel[channel.index()])
+                .collect();
+            let file = split_file(output_file, channel);
+            DXImage::new_2d(DXGI_FORMAT_R8_UNORM, metadata.width, metadata.height, 1, 1, &grey)?
+                .save(0, ImageFormat::Png, &file)?;
+            event!(TRACE, "Wrote channel {channel} to {file}");
+            Ok(file)
+        })
+        .collect()
+}
+
+impl Display for Channel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::R => "r",
+            Self::G => "g",
+            Self::B => "b",
+            Self::A => "a",
+        })
+    }
+}
+
+impl FromStr for Channel {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|channel| channel.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| Error::message(format!("Unknown channel {s}")))
+    }
+}
+
+impl Display for ChannelSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Image {
+                file,
+                channel: Channel::R,
+            } => f.write_str(file),
+            Self::Image { file, channel } => write!(f, "{file}:{channel}"),
+            Self::Constant(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+impl FromStr for ChannelSource {
+    type Err = Error;
+
+    /// A number from 0 to 255, or a file optionally followed by `:r`, `:g`,
+    /// `:b` or `:a`.
+    fn from_str(s: &str) -> Result<Self> {
+        if let Ok(value) = s.parse() {
+            return Ok(Self::Constant(value));
+        }
+
+        // Drive letters also use a colon, only a channel name counts
+        match s.rsplit_once(':') {
+            Some((file, channel)) if channel.len() == 1 && !file.is_empty() => Ok(Self::Image {
+                file:    file.to_owned(),
+                channel: channel.parse()?,
+            }),
+            _ if !s.is_empty() => Ok(Self::Image {
+                file:    s.to_owned(),
+                channel: Channel::R,
+            }),
+            _ => error_message("Empty channel source"),
+        }
+    }
+}
+
+impl Display for ChannelRecipe {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (channel, source) in Channel::ALL.iter().zip(&self.channels) {
+            if *channel != Channel::R {
+                f.write_str(",")?;
+            }
+            write!(f, "{channel}={source}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ChannelRecipe {
+    type Err = Error;
+
+    /// `split`, or comma separated `channel=source` entries. Channels that
+    /// aren't mentioned are 0, except alpha which is 255.
+    fn from_str(s: &str) -> Result<Self> {
+        if s.eq_ignore_ascii_case(SPLIT_RECIPE) {
+            return Ok(Self::split());
+        }
+
+        let mut channels = [
+            ChannelSource::Constant(0),
+            ChannelSource::Constant(0),
+            ChannelSource::Constant(0),
+            ChannelSource::Constant(255),
+        ];
+        for entry in s.split(',') {
+            let (channel, source) = entry
+                .split_once('=')
+                .ok_or_else(|| Error::message(format!("Expected channel=source, got {entry}")))?;
+            let channel: Channel = channel.trim().parse()?;
+            channels[channel.index()] = source.trim().parse()?;
+        }
+
+        Ok(Self { channels })
+    }
+}
+
+#[test]
+fn test_channel_recipe() {
+    let recipe: ChannelRecipe = "r={name}_gloss.png, g=C:\\masks\\ao.png:g, b=0".parse().unwrap();
+
+    assert_eq!(recipe.channels[0], ChannelSource::Image {
+        file:    "{name}_gloss.png".to_owned(),
+        channel: Channel::R,
+    });
+    assert_eq!(recipe.channels[1], ChannelSource::Image {
+        file:    "C:\\masks\\ao.png".to_owned(),
+        channel: Channel::G,
+    });
+    assert_eq!(recipe.channels[2], ChannelSource::Constant(0));
+    assert_eq!(recipe.channels[3], ChannelSource::Constant(255));
+    assert_eq!(recipe.to_string().parse::<ChannelRecipe>().unwrap(), recipe);
+    assert!("x=0".parse::<ChannelRecipe>().is_err());
+
+    assert_eq!(
+        ChannelRecipe::source_file("{name}_gloss.png", Utf8Path::new("dir/wall_m.png")),
+        Utf8PathBuf::from("dir/wall_m_gloss.png")
+    );
+    assert_eq!(
+        ChannelRecipe::source_file("{name}_g.png", Utf8Path::new("dir/wall_m.png")),
+        split_file(Utf8Path::new("dir/wall_m.png"), Channel::G)
+    );
+}
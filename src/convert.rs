@@ -1,424 +1,1110 @@
+use std::borrow::Cow;
 use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufWriter;
 use std::time::{Duration, Instant};
 
 use camino::{Utf8Path, Utf8PathBuf};
-use image::ImageBuffer;
-use windows::Win32::Graphics::Dxgi::Common::*;
+use windows::Win32::Graphics::Dxgi::Common::{
+    DXGI_FORMAT_R8G8B8A8_UNORM,
+    DXGI_FORMAT_R8G8B8A8_UNORM_SRGB,
+};
 
-use crate::dxtex::DXImage;
-use crate::files::{FileFormat, FileGroup, FileStatus, FileType, Categorized};
-use crate::formats::ColorPlanes;
-use crate::images::{DxImport, ImageRs};
+use crate::atlas::{self, AtlasLayout};
+use crate::channels;
+use crate::cubemap;
+use crate::dxtex::{DXImage, TEX_FILTER_FLAGS};
+use crate::embed::{self, EmbeddedFormat};
+use crate::files::{
+    as_images, as_textures, base_name, FileGroup, FileStatus, FileType, OutputFormat, Scanned,
+};
+use crate::formats::{ColorPlanes, ImageFormat};
+use crate::images::Warnings;
+use crate::lut::{self, CubeLut};
+use crate::metrics::CompressionError;
+use crate::mips;
+use crate::naming;
+use crate::normals;
+use crate::options::{AlphaMode, AspectMode, ColorOptions, ImportOptions, Options};
 use crate::prelude::*;
+use crate::rgb::{CONTAINER_PNG, PIXEL_FORMAT_BGR, WIC};
+use crate::sidecar::{sidecar_file, Sidecar};
+use crate::tonemap;
 
 pub enum TaskResult {
     Started(usize),
-    Result(FileGroup<Categorized>, Duration),
+    /// A finished group, with its outputs and warnings or the error that
+    /// stopped it
+    Result(FileGroup<Scanned>, FileStatus, Duration),
     Complete(Duration),
 }
 
-pub fn convert(group: FileGroup<Categorized>) -> Option<TaskResult> {
-    todo!()
-
-    // let outputs = match &group.outputs {
-    //     Ok(outputs) => outputs,
-    //     Err(error) => return None,
-    // };
-    // {
-    //     let status = group.status.lock();
-    //     if !matches!(&*status, FileStatus::Ok | FileStatus::Warnings(_)) {
-    //         return None;
-    //     }
-    // }
-    // if group.file_type == FileType::Texture {
-    //     Some(export_texture(group))
-    // } else {
-    //     Some(import_images(group))
-    // }
+/// Converts a scanned group in whichever direction its file type calls for.
+/// Groups without usable inputs or a format are skipped and return `None`.
+#[must_use]
+pub fn convert(group: FileGroup<Scanned>, options: &Options) -> Option<TaskResult> {
+    let start = Instant::now();
+
+    let (input_warnings, inputs) = match &group.input {
+        FileStatus::Unknown => return None,
+        FileStatus::Error(error) => {
+            let status = FileStatus::Error(error.clone());
+            return Some(TaskResult::Result(group, status, start.elapsed()));
+        }
+        FileStatus::Ok(warnings, inputs) => (warnings.clone(), inputs.clone()),
+    };
+
+    let mut warnings = input_warnings;
+    let (format, outputs, provenance) = match &group.output {
+        OutputFormat::Exact {
+            format,
+            outputs,
+            provenance,
+        } => (*format, outputs.clone(), provenance),
+        OutputFormat::Candidates(candidates, provenance) if !candidates.is_empty() => {
+            let format = candidates[0];
+            let outputs = match group.file_type {
+                FileType::Texture => as_images(&format, &inputs),
+                FileType::Image(_) | FileType::Lut => as_textures(&format, &inputs),
+            };
+            warnings.push("Guessed the file format based on file size");
+            (format, outputs, provenance)
+        }
+        OutputFormat::Candidates(..) | OutputFormat::Unknown => return None,
+    };
+    // The scan names images with the default options
+    let outputs = match group.file_type {
+        FileType::Texture => {
+            let image_format = options.export.image_format_for(&format);
+            match naming::export_files(&format, image_format, &inputs, &options.export) {
+                Ok(outputs) => outputs,
+                Err(error) => {
+                    let status = FileStatus::Error(error.to_string());
+                    return Some(TaskResult::Result(group, status, start.elapsed()));
+                }
+            }
+        }
+        FileType::Image(_) | FileType::Lut => outputs,
+    };
+
+    if options.explain {
+        warnings.push(format!("{format}\n{provenance}"));
+    }
+
+    let result = match group.file_type {
+        FileType::Texture => export_texture(format, &inputs, &outputs, options),
+        FileType::Image(_) | FileType::Lut => import_image(
+            format,
+            provenance.header.as_deref(),
+            &inputs,
+            &outputs,
+            options,
+        )
+        .map_err(|error| {
+            Error::message(format!(
+                "Failed to import to {}: {error}",
+                format.dxgi_format.display()
+            ))
+        }),
+    };
+
+    let status = match result {
+        Ok(output_warnings) => {
+            warnings.extend(output_warnings);
+            FileStatus::Ok(warnings, outputs)
+        }
+        Err(error) => FileStatus::Error(error.to_string()),
+    };
+
+    Some(TaskResult::Result(group, status, start.elapsed()))
+}
+
+fn save_rgb(image: &DXImage, file: &Utf8Path) -> Result<()> {
+    let wic = WIC::new()?;
+    let bitmap = wic.bitmap_from_directxtex(image, 0)?;
+    let from_pixel_format = bitmap.pixel_format()?;
+    let rgb = bitmap.to_pixel_format(&from_pixel_format, PIXEL_FORMAT_BGR)?;
+    rgb.save(&file, CONTAINER_PNG)
 }
 
-pub fn task_error(group: FileGroup<Categorized>, error: Error, duration: Duration) -> TaskResult {
-    todo!()
-    //TaskResult::Result(group, duration)
+/// Exports a texture to images and writes the metadata file that lets them be
+/// imported again.
+pub fn export_texture(
+    format: TextureFormat,
+    inputs: &[Utf8PathBuf],
+    outputs: &[Utf8PathBuf],
+    options: &Options,
+) -> Result<Warnings> {
+    let (dimensions, texture_file) = format.best_texture(inputs).ok_or_else(|| {
+        Error::message("Detected a format and it didn't match, the file may be corrupted.")
+    })?;
+    let all_data = std::fs::read(texture_file)?;
+    let texture_data = format.without_header(&all_data);
+
+    let pixel_format = format.dxgi_format.uncompressed_format();
+    let raw_image = DXImage::with_dimensions(
+        format.dxgi_format,
+        dimensions,
+        format.array_size,
+        texture_data,
+    )
+    .map_err(|error| {
+        Error::message(format!(
+            "Loading the texture data from {texture_file} as {} failed: {error}",
+            format.dxgi_format.display()
+        ))
+    })?;
+    let raw_image = if options.export.mip_levels || options.export.dds_chain {
+        full_chain(&format, dimensions, inputs, raw_image)?
+    } else {
+        raw_image
+    };
+
+    let output_image = raw_image.to_format(pixel_format).map_err(|error| {
+        Error::message(format!(
+            "Decompressing texture data from {texture_file} to pixel format {} failed: {error}",
+            pixel_format.display()
+        ))
+    })?;
+
+    // LUT entries are data, the colour options don't apply to them
+    let lut = if format.is_lut() {
+        Some(CubeLut::from_texture(&output_image, &format)?)
+    } else {
+        None
+    };
+
+    let normal_map = normals::is_normal_map(inputs, &options.normals);
+    let output_image = if normal_map {
+        normals::export(&output_image, &options.normals)?
+    } else {
+        export_colors(&output_image, &options.color)?
+    };
+
+    let metadata = output_image.metadata()?;
+    let dds_chain = options.export.dds_chain;
+    let cube_layout = cubemap::uses_layout(&format, options.cubemap) && !dds_chain;
+    let atlas = options
+        .atlas
+        .or_else(|| format.is_2d_lut().then_some(format.array_size))
+        .filter(|_| format.array_size > 1 && !cube_layout && !dds_chain)
+        .map(|columns| AtlasLayout::new(columns, format.array_size));
+    let layout_outputs;
+    let (output_image, outputs, image_format) = if dds_chain {
+        let output_file = outputs
+            .first()
+            .ok_or_else(|| Error::message("Internal error: no output file for the DDS file"))?;
+        layout_outputs = [Utf8PathBuf::from(base_name(output_file)).with_extension("dds")];
+
+        (output_image, &layout_outputs[..], ImageFormat::Dds)
+    } else if cube_layout {
+        let image_format = if format.dxgi_format.is_hdr() {
+            ImageFormat::OpenExr
+        } else {
+            ImageFormat::Png
+        };
+        let output_file = outputs
+            .first()
+            .ok_or_else(|| Error::message("Internal error: no output file for the cubemap"))?;
+        layout_outputs = [cubemap::layout_file(output_file, image_format)];
+
+        let image = cubemap::to_layout(&output_image, options.cubemap)?
+            .convert(metadata.format, TEX_FILTER_FLAGS::default())?;
+        (Cow::Owned(image), &layout_outputs[..], image_format)
+    } else if let Some(layout) = atlas {
+        let image_format = atlas::image_format(&format, options.export.image_format_for(&format));
+        let output_file = outputs
+            .first()
+            .ok_or_else(|| Error::message("Internal error: no output file for the atlas"))?;
+        layout_outputs = [atlas::atlas_file(output_file, image_format)];
+
+        let image = atlas::to_atlas(&output_image, layout)?;
+        (Cow::Owned(image), &layout_outputs[..], image_format)
+    } else {
+        (output_image, outputs, options.export.image_format_for(&format))
+    };
+
+    for directory in outputs.iter().filter_map(|file| file.parent()) {
+        if !directory.as_str().is_empty() {
+            std::fs::create_dir_all(directory).map_err(|error| {
+                Error::message(format!("Failed to create the directory {directory}: {error}"))
+            })?;
+        }
+    }
+
+    let rgb = format.planes() == ColorPlanes::Rgb && image_format == ImageFormat::Png;
+    for (array_index, output_file) in outputs.iter().enumerate() {
+        if dds_chain {
+            // The texture data as it is, the colour options don't apply
+            raw_image.save_dds(output_file).map_err(|error| {
+                Error::message(format!("Failed to save the mip chain as {output_file}: {error}"))
+            })?;
+        } else if rgb {
+            save_rgb(&output_image, output_file).map_err(|error| {
+                Error::message(format!(
+                    "Windows Imaging Component returned an error while saving PNG: {error}"
+                ))
+            })?;
+        } else {
+            output_image
+                .save(array_index, image_format, &output_file)
+                .map_err(|error| {
+                    Error::message(format!(
+                        "Failed to save the file as {output_file} from format {}: {error}",
+                        metadata.format.display()
+                    ))
+                })?;
+        }
+
+        if options.export.mip_levels && image_format != ImageFormat::Dds {
+            save_mip_levels(&output_image, array_index, image_format, rgb, output_file)?;
+        }
+
+        if options.export.split_channels {
+            channels::split(&output_image, array_index, output_file).map_err(|error| {
+                Error::message(format!("Failed to split the channels of {output_file}: {error}"))
+            })?;
+        }
+
+        if options.tonemap.preview && format.dxgi_format.is_hdr() {
+            let preview_file = tonemap::preview_file(output_file);
+            tonemap::to_ldr(&output_image, array_index, &options.tonemap)
+                .and_then(|preview| preview.save(0, ImageFormat::Png, &preview_file))
+                .map_err(|error| {
+                    Error::message(format!("Failed to save the preview {preview_file}: {error}"))
+                })?;
+        }
+    }
+
+    if let (Some(lut), Some(output_file)) = (&lut, outputs.first()) {
+        let cube_file = lut::cube_file(output_file);
+        lut.save(&cube_file)
+            .map_err(|error| Error::message(format!("Failed to save {cube_file}: {error}")))?;
+    }
+
+    let mut warnings = Warnings::new();
+    if options.export.image_format.image_format(&format).is_none() {
+        warnings.push(format!(
+            "{} images can't hold {} values, saved as {} instead",
+            options.export.image_format,
+            format.dxgi_format.display(),
+            format.default_image_format().extensions_str()[0]
+        ));
+    }
+
+    let mut sidecar = Sidecar::new(format, inputs, options)?;
+    sidecar.normal_map = normal_map;
+    sidecar.atlas = atlas;
+    if let Some(output_file) = outputs.first() {
+        let metafile = sidecar_file(output_file);
+        if let Err(error) = sidecar.save(&metafile) {
+            warnings.push(format!("Failed to save {metafile}: {error}"));
+        }
+    }
+
+    let embedded = EmbeddedFormat {
+        id:     sidecar.id,
+        header: sidecar.header,
+    };
+    for output_file in outputs.iter().filter(|file| embed::supported(file)) {
+        if let Err(error) = embed::write(output_file, &embedded) {
+            warnings.push(format!("Failed to store the format in {output_file}: {error}"));
+        }
+    }
+
+    Ok(warnings)
 }
 
-pub fn export_texture(group: FileGroup<Categorized>) -> TaskResult {
-    // let start = Instant::now();
-
-    // let format = match &group.format {
-    //     FileFormat::Ready(format) => format.clone(),
-    //     _ => return task_error(group, Error::Internal, start.elapsed()),
-    // };
-
-    // let (texture_file, mut dimensions) = pick_best_texture(&format, &group.inputs);
-    // let texture_data = match std::fs::read(&texture_file) {
-    //     Err(error) => return task_error(group, error.into(), start.elapsed()),
-    //     Ok(data) => data,
-    // };
-    // let data = format.without_header(&texture_data);
-
-    // if let Some(highres) = format.highres {
-    //     let expected_size =
-    //         dxtex::expected_size_array(format.dxgi_format, dimensions, format.array_size);
-    //     if expected_size != data.len() {
-    //         let expected_size =
-    //             dxtex::expected_size_array(format.dxgi_format, highres, format.array_size);
-    //         if expected_size == data.len() {
-    //             dimensions = highres;
-    //             event!(WARN, "Matched with high-res dimensions");
-    //         } else {
-    //             event!(
-    //                 WARN,
-    //                 "Dimensions don't match, conversion will probably fail"
-    //             );
-    //         }
-    //     }
-    // }
-
-    // let dx = match if format.is_1d() {
-    //     DXImage::new_1d(
-    //         format.dxgi_format,
-    //         dimensions.width,
-    //         format.array_size,
-    //         dimensions.mipmaps,
-    //         data,
-    //     )
-    //     .log_failure_as("new_1d")
-    // } else {
-    //     DXImage::new_2d(
-    //         format.dxgi_format,
-    //         dimensions.width,
-    //         dimensions.height,
-    //         format.array_size,
-    //         dimensions.mipmaps,
-    //         data,
-    //     )
-    //     .log_failure_as("new_2d")
-    // } {
-    //     Err(error) => return task_error(group, error, start.elapsed()),
-    //     Ok(dx) => dx,
-    // };
-
-    // let dx = match dx.map_if(format.dxgi_format.is_compressed(), |dx| {
-    //     dx.decompress()
-    //         .log_failure_with(|| format!("{texture_file}: Decompression failed"))
-    // }) {
-    //     Err(error) => return task_error(group, error, start.elapsed()),
-    //     Ok(dx) => dx,
-    // };
-
-    todo!()
-    // for (array_index, output) in
-    // group.outputs.as_ref().unwrap().into_iter().enumerate() {     if let
-    // Err(error) = save_image(&dx, &format, &output, array_index) {
-    //         return task_error(group, error, start.elapsed());
-    //     }
-    // }
-
-    // TaskResult::Result(group, start.elapsed())
+/// Adds the levels of the SD texture below the HD ones, which the HD texture
+/// doesn't store, when both are among `inputs`.
+fn full_chain(
+    format: &TextureFormat,
+    dimensions: Dimensions,
+    inputs: &[Utf8PathBuf],
+    image: DXImage,
+) -> Result<DXImage> {
+    let standard = format.standard;
+    let sd_file = inputs
+        .iter()
+        .find(|file| format.dimensions_for_file(file) == Some(standard));
+    let sd_file = match sd_file {
+        Some(file) if dimensions != standard => file,
+        _ => return Ok(image),
+    };
+
+    let data = std::fs::read(sd_file)?;
+    let sd_image = DXImage::with_dimensions(
+        format.dxgi_format,
+        standard,
+        format.array_size,
+        format.without_header(&data),
+    )?;
+
+    Ok(mips::join_chain(&image, &sd_image)
+        .log_failure_as("Exporting only the mip levels of the HD texture")
+        .unwrap_or(image))
 }
 
-pub fn save_image(
+/// Saves mip levels 1 and up of one array slice as `name@mipN` next to
+/// `output_file`.
+fn save_mip_levels(
     image: &DXImage,
-    format: &TextureFormat,
-    file: &Utf8Path,
     array_index: usize,
+    image_format: ImageFormat,
+    rgb: bool,
+    output_file: &Utf8Path,
 ) -> Result<()> {
-    event!(INFO, "Saving {file}");
+    let metadata = image.metadata()?;
+    let top = Dimensions {
+        data_size: 0,
+        width:     metadata.width,
+        height:    metadata.height,
+        mipmaps:   1,
+    };
+
+    for level in 1 .. metadata.mipLevels {
+        let (width, height) = mips::level_size(top, level as u8);
+        let data = image.image(array_index * metadata.mipLevels + level)?;
+        let mip = DXImage::new_2d(metadata.format, width, height, 1, 1, &data)?;
+        let mip_file = mips::mip_file(output_file, level as u8);
+
+        if rgb {
+            save_rgb(&mip, &mip_file)
+        } else {
+            mip.save(0, image_format, &mip_file)
+        }
+        .map_err(|error| Error::message(format!("Failed to save {mip_file}: {error}")))?;
+    }
+    Ok(())
+}
+
+/// Applies the colour options to a decoded texture. Formats without an sRGB
+/// variant are saved as they are.
+fn export_colors<'a>(image: &'a DXImage, options: &ColorOptions) -> Result<Cow<'a, DXImage>> {
+    let format = image.metadata()?.format;
+    let image = match options.alpha {
+        AlphaMode::Keep => Cow::Borrowed(image),
+        AlphaMode::Premultiply => Cow::Owned(image.premultiply_alpha(false)?),
+        AlphaMode::Unpremultiply => Cow::Owned(image.premultiply_alpha(true)?),
+    };
+
+    let target = if options.space.is_srgb(format) {
+        format.srgb_format()
+    } else {
+        format.linear_format()
+    };
+    if target == format {
+        Ok(image)
+    } else {
+        Ok(Cow::Owned(image.convert(target, TEX_FILTER_FLAGS::default())?))
+    }
+}
 
+/// Tags an imported image with the colour space of its data for a texture in
+/// `format` and applies the alpha option, before anything filters it.
+fn import_colors(image: DXImage, format: DXGI_FORMAT, options: &ColorOptions) -> Result<DXImage> {
     let metadata = image.metadata()?;
+    let image = if metadata.format.planes() == ColorPlanes::Hdr {
+        image
+    } else {
+        tag_color_space(image, options.space.is_srgb(format))?
+    };
 
-    let ext = file.extension().unwrap();
-    match ext {
-        ext if ext.eq_ignore_ascii_case("dds") => image.save_dds(file),
-        ext if ext.eq_ignore_ascii_case("hdr") => image.save_hdr(array_index, file),
-        ext if ext.eq_ignore_ascii_case("exr") => image.save_exr(array_index, file),
-        ext if ext.eq_ignore_ascii_case("tga") => image.save_tga(array_index, file),
-        ext if ext.eq_ignore_ascii_case("png") => {
-            let data = image.image(array_index)?;
-
-            match metadata.format {
-                DXGI_FORMAT_R8_UNORM => {
-                    let img: ImageBuffer<image::Luma<u8>, _> =
-                        ImageBuffer::from_raw(metadata.width as u32, metadata.height as u32, data)
-                            .log_failure_as("Failed to open image data")
-                            .ok_or(Error::Internal)?;
-                    Ok(img
-                        .save(file)
-                        .log_failure_with(|| format!("Failed to save {file}"))?)
-                }
-                DXGI_FORMAT_R8G8B8A8_UNORM | DXGI_FORMAT_R8G8B8A8_UNORM_SRGB => {
-                    let img: ImageBuffer<image::Rgba<u8>, _> =
-                        ImageBuffer::from_raw(metadata.width as u32, metadata.height as u32, data)
-                            .log_failure_as("Failed to open image data")
-                            .ok_or(Error::Internal)?;
-                    Ok(img
-                        .save(file)
-                        .log_failure_with(|| format!("Failed to save {file}"))?)
-                }
-                format => error_message(format!(
-                    "Trying to save an unsupported format {}",
-                    format.display()
-                )),
-            }
+    let reverse = match options.alpha {
+        AlphaMode::Keep => return Ok(image),
+        AlphaMode::Premultiply => false,
+        AlphaMode::Unpremultiply => true,
+    };
+    let compressed = image.metadata()?.format.is_compressed();
+    image
+        .map_if(compressed, DXImage::decompress)?
+        .premultiply_alpha(reverse)
+}
+
+/// Marks `image` as holding sRGB or linear data without changing the values,
+/// so that DirectXTex converts and filters them correctly. Formats without an
+/// sRGB variant are widened to RGBA first.
+fn tag_color_space(image: DXImage, srgb: bool) -> Result<DXImage> {
+    let format = image.metadata()?.format;
+    let tagged = if srgb {
+        format.srgb_format()
+    } else {
+        format.linear_format()
+    };
+
+    if tagged.is_srgb() == srgb {
+        if tagged != format {
+            image.override_format(tagged)?;
         }
-        _ => error_message("Trying to save with unsupported extension"),
+        Ok(image)
+    } else {
+        let rgba = image
+            .map_if(format.is_compressed(), DXImage::decompress)?
+            .convert(DXGI_FORMAT_R8G8B8A8_UNORM, TEX_FILTER_FLAGS::default())?;
+        rgba.override_format(DXGI_FORMAT_R8G8B8A8_UNORM_SRGB)?;
+        Ok(rgba)
     }
 }
 
-fn pipeline_png<FILE: AsRef<Utf8Path> + std::fmt::Display>(
-    format: &TextureFormat,
-    mut inputs: impl Iterator<Item = FILE>,
-    (dim_primary, dim_secondary): (Dimensions, Option<Dimensions>),
-) -> impl Iterator<Item = Result<(Vec<u8>, Option<Vec<u8>>)>> {
-    std::iter::from_fn(move || {
-        inputs.next().and_then(|input| {
-            let span = span!(TRACE, "pipeline_png", %input);
-            let _entered = span.enter();
-
-            match image::open(input.as_ref()) {
-                Err(error) => Some(Err(error.into())),
-                Ok(image) => {
-                    event!(
-                        TRACE,
-                        "Resizing primary to {}x{}",
-                        dim_primary.width,
-                        dim_primary.height
-                    );
-                    let primary = if image.width() as usize != dim_primary.width
-                        || image.height() as usize != dim_primary.height
-                    {
-                        image
-                            .resize_exact(
-                                dim_primary.width as u32,
-                                dim_primary.height as u32,
-                                crate::IMAGERS_RESIZE_FILTER,
-                            )
-                            .into_bytes()
-                    } else {
-                        event!(TRACE, "It's already correct");
-                        image.clone().into_bytes()
-                    };
-                    let secondary = dim_secondary.map(|dim| {
-                        event!(TRACE, "Resizing secondary to {}x{}", dim.width, dim.height);
-                        if image.width() as usize != dim.width
-                            || image.height() as usize != dim.height
-                        {
-                            image
-                                .resize_exact(
-                                    dim.width as u32,
-                                    dim.height as u32,
-                                    crate::IMAGERS_RESIZE_FILTER,
-                                )
-                                .into_bytes()
-                        } else {
-                            event!(TRACE, "It's already correct");
-                            image.clone().into_bytes()
-                        }
-                    });
-                    Some(Ok((primary, secondary)))
-                }
-            }
-        })
-    })
+fn bring_dx_to_format<'a>(
+    image: &'a DXImage,
+    format: DXGI_FORMAT,
+    dimensions: Dimensions,
+    options: &ImportOptions,
+) -> Result<(Cow<'a, DXImage>, Warnings)> {
+    let mut warnings = Warnings::new();
+    let mut metadata = image.metadata()?;
+    let image = if metadata.format == format
+        && (metadata.width, metadata.height) == (dimensions.width, dimensions.height)
+    {
+        return Ok((Cow::Borrowed(image), warnings));
+    } else if metadata.format.is_compressed() {
+        Cow::Owned(image.decompress()?)
+    } else {
+        Cow::Borrowed(image)
+    };
+
+    metadata = image.metadata()?;
+    let image = if metadata.format == format {
+        image
+    } else {
+        Cow::Owned(image.convert(format, TEX_FILTER_FLAGS::default())?)
+    };
+
+    let image = fit_aspect_ratio(image, dimensions, options)?;
+
+    let metadata = image.metadata()?;
+    if (metadata.width, metadata.height) == (dimensions.width, dimensions.height) {
+        Ok((image, warnings))
+    } else {
+        warnings.push(format!(
+            "Wrong dimensions ({}x{}), resized to {}x{}",
+            metadata.width, metadata.height, dimensions.width, dimensions.height
+        ));
+        event!(
+            WARN,
+            "Resizing to {}x{} from {}x{}",
+            dimensions.width,
+            dimensions.height,
+            metadata.width,
+            metadata.height
+        );
+        Ok((
+            Cow::Owned(image.resize_with(
+                dimensions.width,
+                dimensions.height,
+                options.resize_filter.dxtex() | TEX_FILTER_FLAGS::srgb_for(format),
+            )?),
+            warnings,
+        ))
+    }
 }
 
-fn pipeline_dx<FILE: AsRef<Utf8Path> + std::fmt::Display>(
-    format: &TextureFormat,
-    mut inputs: impl Iterator<Item = FILE>,
-    (dim_primary, dim_secondary): (Dimensions, Option<Dimensions>),
-) -> impl Iterator<Item = Result<(Vec<u8>, Option<Vec<u8>>)>> {
-    std::iter::from_fn(move || {
-        inputs.next().and_then(|input| {
-            let span = span!(TRACE, "pipeline_dx", %input);
-            let _entered = span.enter();
-
-            match DXImage::load(input.as_ref()) {
-                Err(error) => Some(Err(error.into())),
-                Ok(image) => {
-                    let metadata = image.metadata().unwrap();
-
-                    event!(
-                        TRACE,
-                        "Resizing primary to {}x{}",
-                        dim_primary.width,
-                        dim_primary.height
-                    );
-                    let primary = if metadata.width != dim_primary.width
-                        || metadata.width as usize != dim_primary.height
-                    {
-                        image
-                            .resize(dim_primary.width, dim_primary.height)
-                            .unwrap()
-                            .pixels()
-                            .unwrap()
-                    } else {
-                        event!(TRACE, "It's already correct");
-                        image.pixels().unwrap()
-                    };
-                    let secondary = dim_secondary.map(|dim| {
-                        event!(TRACE, "Resizing secondary to {}x{}", dim.width, dim.height);
-                        if metadata.width != dim.width || metadata.height != dim.height {
-                            image
-                                .resize(dim.width, dim.height)
-                                .unwrap()
-                                .pixels()
-                                .unwrap()
-                        } else {
-                            event!(TRACE, "It's already correct");
-                            image.pixels().unwrap()
-                        }
-                    });
-                    Some(Ok((primary, secondary)))
-                }
+/// Crops or pads an uncompressed image to the aspect ratio of `dimensions`,
+/// depending on [`ImportOptions::aspect`]. Padded images come back at the
+/// final size, everything else may still need resizing.
+fn fit_aspect_ratio<'a>(
+    image: Cow<'a, DXImage>,
+    dimensions: Dimensions,
+    options: &ImportOptions,
+) -> Result<Cow<'a, DXImage>> {
+    let metadata = image.metadata()?;
+    let (width, height) = (metadata.width, metadata.height);
+    let (target_width, target_height) = (dimensions.width, dimensions.height);
+
+    if width * target_height == height * target_width {
+        return Ok(image);
+    }
+    let wider = width * target_height > height * target_width;
+
+    match options.aspect {
+        AspectMode::Stretch => Ok(image),
+        AspectMode::Reject => error_message(format!(
+            "The image is {width}x{height}, which doesn't have the aspect ratio of the \
+             {target_width}x{target_height} texture"
+        )),
+        AspectMode::Crop => {
+            let (crop_width, crop_height) = if wider {
+                ((height * target_width / target_height).max(1), height)
+            } else {
+                (width, (width * target_height / target_width).max(1))
+            };
+            let (left, top) = ((width - crop_width) / 2, (height - crop_height) / 2);
+
+            let pixels = image.image(0)?;
+            let pixel_size = pixels.len() / (width * height);
+            let mut cropped = Vec::with_capacity(crop_width * crop_height * pixel_size);
+            for row in pixels.chunks_exact(width * pixel_size).skip(top).take(crop_height) {
+                cropped.extend_from_slice(
+                    &row[left * pixel_size .. (left + crop_width) * pixel_size],
+                );
             }
-        })
-    })
-}
 
-pub fn load_image(file: &Utf8Path, dimensions: Dimensions) -> Result<Vec<u8>> {
-    event!(INFO, "Reading {file}");
-
-    let ext = file.extension().unwrap();
-    match ext {
-        ext if ext.eq_ignore_ascii_case("dds") => dxtex::load_dds(file)
-            .and_then(|dx| dx.resize(dimensions.width, dimensions.height))
-            .and_then(|dx| dx.pixels()),
-        ext if ext.eq_ignore_ascii_case("hdr") => dxtex::load_hdr(file)
-            .and_then(|dx| dx.resize(dimensions.width, dimensions.height))
-            .and_then(|dx| dx.pixels()),
-        ext if ext.eq_ignore_ascii_case("exr") => dxtex::load_exr(file)
-            .and_then(|dx| dx.resize(dimensions.width, dimensions.height))
-            .and_then(|dx| dx.pixels()),
-        ext if ext.eq_ignore_ascii_case("tga") => dxtex::load_tga(file)
-            .and_then(|dx| dx.resize(dimensions.width, dimensions.height))
-            .and_then(|dx| dx.pixels()),
-        ext if ext.eq_ignore_ascii_case("png") => {
-            let image = image::open(file)?;
-            Ok(image
-                .resize_exact(
-                    dimensions.width as u32,
-                    dimensions.height as u32,
-                    crate::IMAGERS_RESIZE_FILTER,
-                )
-                .into_bytes())
+            Ok(Cow::Owned(DXImage::new_2d(
+                metadata.format,
+                crop_width,
+                crop_height,
+                1,
+                1,
+                &cropped,
+            )?))
+        }
+        AspectMode::Pad(color) => {
+            let (fit_width, fit_height) = if wider {
+                (target_width, (height * target_width / width).max(1))
+            } else {
+                ((width * target_height / height).max(1), target_height)
+            };
+            let (left, top) = (
+                (target_width - fit_width) / 2,
+                (target_height - fit_height) / 2,
+            );
+
+            let fitted = image
+                .resize_with(fit_width, fit_height, options.resize_filter.dxtex())?
+                .image(0)?;
+            let pixel = DXImage::new_2d(DXGI_FORMAT_R8G8B8A8_UNORM, 1, 1, 1, 1, &color)?
+                .map_if(metadata.format != DXGI_FORMAT_R8G8B8A8_UNORM, |pixel| {
+                    pixel.convert(metadata.format, TEX_FILTER_FLAGS::default())
+                })?
+                .image(0)?;
+            let pixel_size = pixel.len();
+
+            let mut padded = pixel.repeat(target_width * target_height);
+            let row_len = target_width * pixel_size;
+            for (y, row) in fitted.chunks_exact(fit_width * pixel_size).enumerate() {
+                let start = (top + y) * row_len + left * pixel_size;
+                padded[start .. start + row.len()].copy_from_slice(row);
+            }
+
+            Ok(Cow::Owned(DXImage::new_2d(
+                metadata.format,
+                target_width,
+                target_height,
+                1,
+                1,
+                &padded,
+            )?))
         }
-        _ => error_message("Trying to load an unsupported extension"),
     }
 }
 
-pub fn pick_best_texture<'a>(
-    format: &TextureFormat,
-    inputs: &'a [Utf8PathBuf],
-) -> (&'a Utf8Path, Dimensions) {
-    let mut best: Option<(&Utf8Path, Dimensions)> = None;
-
-    for file in inputs {
-        let size = std::fs::metadata(&file).unwrap().len() as usize;
-        if let Some(highres) = format.highres {
-            if highres.data_size == size {
-                return (file, highres);
+fn load_source(file: &Utf8Path, options: &Options) -> Result<DXImage> {
+    match &options.import.pack {
+        Some(recipe) => recipe.pack(file),
+        None => DXImage::load(file),
+    }
+}
+
+/// Applies the colour options to a loaded image. Float images are only
+/// requested for data that must not go through the sRGB curve.
+fn prepare_image(
+    image: DXImage,
+    compressed_format: DXGI_FORMAT,
+    pixel_format: DXGI_FORMAT,
+    options: &Options,
+) -> Result<DXImage> {
+    let image = import_colors(image, compressed_format, &options.color)?;
+
+    let format = image.metadata()?.format;
+    if pixel_format == normals::FLOAT_FORMAT && format.is_srgb() {
+        image.override_format(format.linear_format())?;
+    }
+
+    Ok(image)
+}
+
+/// Loads hand made mip levels for every image, see [`mips`]. Returns the whole
+/// chain of `dimensions`, or `None` when there are no mip levels.
+fn load_user_mips(
+    compressed_format: DXGI_FORMAT,
+    pixel_format: DXGI_FORMAT,
+    dimensions: Dimensions,
+    images: &[Utf8PathBuf],
+    mip_files: &[Utf8PathBuf],
+    options: &Options,
+) -> Result<Option<DXImage>> {
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut with_mips = 0;
+
+    for file in images {
+        let dx = load_source(file, options)?;
+        let dx = prepare_image(dx, compressed_format, pixel_format, options)?;
+        let metadata = dx.metadata()?;
+        let files = mips::mip_files_for(file, mip_files);
+
+        if metadata.mipLevels == 1 && files.is_empty() {
+            continue;
+        }
+        with_mips += 1;
+
+        if (metadata.width, metadata.height) != (dimensions.width, dimensions.height) {
+            return error_message(format!(
+                "{file} is {}x{}, images with mip levels must be {}x{}",
+                metadata.width, metadata.height, dimensions.width, dimensions.height
+            ));
+        }
+
+        if metadata.mipLevels > 1 {
+            if metadata.mipLevels < usize::from(dimensions.mipmaps) {
+                return error_message(format!(
+                    "{file} has {} mip levels, the texture needs {}",
+                    metadata.mipLevels, dimensions.mipmaps
+                ));
+            }
+            let dx = dx.to_format(pixel_format)?;
+            for level in 0 .. usize::from(dimensions.mipmaps) {
+                buffer.extend(dx.image(level)?);
+            }
+            continue;
+        }
+
+        buffer.extend(dx.to_format(pixel_format)?.image(0)?);
+        for level in 1 .. dimensions.mipmaps {
+            let mip_file = files
+                .iter()
+                .find_map(|(found, mip_file)| (*found == level).then_some(*mip_file))
+                .ok_or_else(|| {
+                    Error::message(format!(
+                        "Missing mip level {level}, expected {}",
+                        mips::mip_file(file, level)
+                    ))
+                })?;
+
+            let mip = DXImage::load(mip_file)?;
+            let mip = prepare_image(mip, compressed_format, pixel_format, options)?;
+            let metadata = mip.metadata()?;
+            let (width, height) = mips::level_size(dimensions, level);
+            if (metadata.width, metadata.height) != (width, height) {
+                return error_message(format!(
+                    "{mip_file} is {}x{}, mip level {level} must be {width}x{height}",
+                    metadata.width, metadata.height
+                ));
             }
+            buffer.extend(mip.to_format(pixel_format)?.image(0)?);
         }
-        if size == format.standard.data_size || size == format.sd_file_len() {
-            best = Some((file, format.standard));
+
+        if let Some((level, extra)) = files.iter().find(|(level, _)| *level >= dimensions.mipmaps) {
+            return error_message(format!(
+                "{extra} is mip level {level}, the texture only has {}",
+                dimensions.mipmaps
+            ));
         }
     }
 
-    match best {
-        None => {
-            event!(ERROR, ?format, ?inputs, "No 'best' file found for");
-            (inputs.first().unwrap(), format.standard)
+    match with_mips {
+        0 => Ok(None),
+        count if count == images.len() => {
+            DXImage::with_dimensions(pixel_format, dimensions, images.len(), &buffer).map(Some)
         }
-        Some(best) => best,
+        _ => error_message("Either all or none of the images of an array need mip levels"),
     }
 }
 
-#[cfg(disabled)]
-pub fn import_images(group: FileGroup) -> TaskResult {
-    let start = Instant::now();
-
-    let format = match &group.format {
-        FileFormat::Final(format) => format.clone(),
-        _ => return task_error(group, Error::Internal, start.elapsed()),
-    };
+/// Loads an array texture from one image, cut into slices by `slice`: a
+/// cubemap layout or an atlas.
+fn load_sliced_image(
+    compressed_format: DXGI_FORMAT,
+    pixel_format: DXGI_FORMAT,
+    dimensions: Dimensions,
+    file: &Utf8Path,
+    options: &Options,
+    slice: impl FnOnce(&DXImage) -> Result<DXImage>,
+) -> Result<(DXImage, Warnings)> {
+    let mut warnings = Warnings::new();
+    let image = load_source(file, options)?;
+    let image = prepare_image(image, compressed_format, pixel_format, options)?;
+    let slices = slice(&image)?;
 
-    let dimensions = if let Some(highres) = format.highres {
-        (highres, Some(format.standard))
+    let metadata = slices.metadata()?;
+    let slices = if (metadata.width, metadata.height) == (dimensions.width, dimensions.height) {
+        slices
     } else {
-        (format.standard, None)
+        warnings.push(format!(
+            "Wrong slice size ({}x{}), resized to {}x{}",
+            metadata.width, metadata.height, dimensions.width, dimensions.height
+        ));
+        slices.resize_with(
+            dimensions.width,
+            dimensions.height,
+            options.import.resize_filter.dxtex() | TEX_FILTER_FLAGS::srgb_for(metadata.format),
+        )?
     };
 
-    // let dimensions = format.highres.unwrap_or(format.standard);
-    let mut texture_data = Vec::new();
+    let slices = slices.to_format(pixel_format)?.into_owned();
+    Ok((slices, warnings))
+}
+
+/// The grid a single image for an array texture is cut along, from its
+/// metadata file or the atlas option. LUTs default to a strip.
+fn atlas_layout(format: &TextureFormat, file: &Utf8Path, options: &Options) -> Option<AtlasLayout> {
+    // DDS files hold the whole array
+    let single = dxtex::metadata(file).map_or(false, |metadata| metadata.arraySize == 1);
+    if format.array_size < 2 || !single {
+        return None;
+    }
+
+    Sidecar::load(&sidecar_file(file))
+        .ok()
+        .and_then(|sidecar| sidecar.atlas)
+        .filter(|layout| layout.slices == format.array_size)
+        .or_else(|| {
+            options
+                .atlas
+                .or_else(|| format.is_2d_lut().then_some(format.array_size))
+                .map(|columns| AtlasLayout::new(columns, format.array_size))
+        })
+}
+
+/// Loads a LUT texture from a `.cube` file, resampled to the size of `format`.
+fn load_lut(
+    format: &TextureFormat,
+    pixel_format: DXGI_FORMAT,
+    file: &Utf8Path,
+) -> Result<(DXImage, Warnings)> {
+    let mut warnings = Warnings::new();
+    let lut = CubeLut::load(file)?;
+    if lut.size != format.standard.width {
+        warnings.push(format!(
+            "Resampled the LUT from {} to {} entries per channel",
+            lut.size, format.standard.width
+        ));
+    }
+
+    let image = lut.to_texture(format)?.to_format(pixel_format)?.into_owned();
+    Ok((image, warnings))
+}
+
+/// Loads one image per array slice, or a single DDS file with every slice.
+/// A file that already holds the texture in its own format is passed through
+/// with its mip levels.
+fn load_image_array(
+    format: &TextureFormat,
+    pixel_format: DXGI_FORMAT,
+    images: &[Utf8PathBuf],
+    options: &Options,
+) -> Result<(DXImage, Warnings)> {
+    let (array_size, compressed_format) = (format.array_size, format.dxgi_format);
+    let dimensions = format.dimensions();
+    let mut warnings = Warnings::new();
+    let mut buffer: Vec<u8> = Vec::with_capacity(dimensions.data_size);
 
-    let pipeline: Vec<Result<(Vec<u8>, Option<Vec<u8>>)>> =
-        match group.inputs.first().as_ref().unwrap().extension().unwrap() {
-            ext if ext.eq_ignore_ascii_case("png") => {
-                pipeline_png(&format, group.inputs.iter(), dimensions).collect()
+    for file in images {
+        let dx = load_source(file, options).log_failure()?;
+        let metadata = dx.metadata().log_failure()?;
+
+        if images.len() == 1 && metadata.format == compressed_format {
+            let same_slices = metadata.arraySize == array_size;
+            match mips::layout_mismatch(format, &metadata) {
+                None => return Ok((dx, warnings)),
+                Some(mismatch) if same_slices && compressed_format.is_compressed() => {
+                    warnings.push(format!("Compressed {file} again because {mismatch}"));
+                }
+                // Wrong array sizes are reported below, uncompressed images
+                // only need their mip levels generated
+                Some(_) => {}
+            }
+        }
+
+        let slices = match (images.len(), metadata.arraySize) {
+            (1, slices) if slices == array_size => slices,
+            (count, _) if count == array_size => 1,
+            (1, slices) if slices > 1 => {
+                return error_message(format!(
+                    "{file} has {slices} array slices, the texture has {array_size}"
+                ));
+            }
+            (count, _) => {
+                return error_message(format!(
+                    "This texture contains {array_size} images and only {count} files were provided"
+                ));
             }
-            _ => pipeline_dx(&format, group.inputs.iter(), dimensions).collect(),
         };
 
-    for result in pipeline {
-        texture_data.extend(match load_image(input, dimensions) {
-            Err(error) => return task_error(group, error, start.elapsed()),
-            Ok(data) => data,
-        });
+        let dx = prepare_image(dx, compressed_format, pixel_format, options)?;
+
+        let (image, input_warnings) =
+            bring_dx_to_format(&dx, pixel_format, dimensions, &options.import).log_failure()?;
+        warnings.extend(input_warnings);
+        let levels = image.metadata()?.mipLevels;
+        for slice in 0 .. slices {
+            buffer.extend(image.image(slice * levels).log_failure()?);
+        }
     }
 
-    let expected_size =
-        dxtex::expected_size_array(format.dxgi_format, dimensions, format.array_size);
-    if expected_size != texture_data.len() {
-        event!(ERROR, expected_size, have_size = %texture_data.len(), ?group.inputs);
+    DXImage::with_dimensions(
+        pixel_format,
+        Dimensions {
+            mipmaps: 1,
+            ..dimensions
+        },
+        array_size,
+        &buffer,
+    )
+    .log_failure()
+    .map(|img| (img, warnings))
+}
+
+/// The layout of `format` at the size of `file` for
+/// [`ImportOptions::keep_resolution`], or `None` if the sizes already match.
+fn custom_resolution(format: &TextureFormat, file: &Utf8Path) -> Result<Option<TextureFormat>> {
+    let metadata = dxtex::metadata(file)?;
+    let (width, height) = (metadata.width, metadata.height);
+    let top = format.dimensions();
+
+    if (width, height) == (top.width, top.height) {
+        Ok(None)
+    } else if width > usize::from(u16::MAX) || height > usize::from(u16::MAX) {
+        error_message(format!("{file} is {width}x{height}, too large for a texture header"))
+    } else {
+        let custom = format.with_resolution(width, height);
+        event!(INFO, "Importing {file} as {custom} instead of {format}");
+        Ok(Some(custom))
     }
+}
+
+/// Imports images as `format`. The raw `header` from a metadata file or the
+/// original texture is preferred over the one stored in the registry.
+pub fn import_image(
+    format: TextureFormat,
+    header: Option<&str>,
+    inputs: &[Utf8PathBuf],
+    outputs: &[Utf8PathBuf],
+    options: &Options,
+) -> Result<Warnings> {
+    let span = span!(TRACE, "import_image", ?format);
+    let _enter = span.enter();
 
-    let dx = match if format.is_1d() {
-        DXImage::new_1d(
+    let (images, mip_files) = mips::partition(inputs);
+    let normal_map = normals::is_normal_map(&images, &options.normals);
+    let pixel_format = if normal_map {
+        normals::FLOAT_FORMAT
+    } else {
+        format.dxgi_format.uncompressed_format()
+    };
+
+    let cube_layout = images.len() == 1 && cubemap::uses_layout(&format, options.cubemap);
+    let cube_file = match images.as_slice() {
+        [file] if lut::is_cube_file(file) => Some(file),
+        _ => None,
+    };
+    let atlas = match images.as_slice() {
+        [file] if !cube_layout && cube_file.is_none() => atlas_layout(&format, file, options),
+        _ => None,
+    };
+    // A texture that was passed through already has its mip levels
+    let sliced = cube_layout || atlas.is_some() || cube_file.is_some();
+
+    // The format before resizing, which has a raw header in the registry
+    let header_format = format;
+    let custom = match images.first() {
+        Some(file) if options.import.keep_resolution && !sliced && !format.is_lut() => {
+            custom_resolution(&format, file)?
+        }
+        _ => None,
+    };
+    let format = custom.unwrap_or(format);
+    let dimensions = format.dimensions();
+    let (image, mut warnings) = if let Some(file) = cube_file {
+        load_lut(&format, pixel_format, file)
+    } else if cube_layout {
+        load_sliced_image(
             format.dxgi_format,
-            dimensions.width,
-            format.array_size,
-            1,
-            &texture_data,
+            pixel_format,
+            dimensions,
+            &images[0],
+            options,
+            |image| cubemap::from_layout(image, options.cubemap),
+        )
+    } else if let Some(layout) = atlas {
+        load_sliced_image(
+            format.dxgi_format,
+            pixel_format,
+            dimensions,
+            &images[0],
+            options,
+            |image| {
+                let image = image.to_format(pixel_format)?;
+                atlas::from_atlas(&image, layout)
+            },
         )
-        .log_failure_as("new_1d")
     } else {
-        DXImage::new_2d(
+        load_image_array(&format, pixel_format, &images, options)
+    }
+    .log_failure()
+    .map_err(|error| Error::message(format!("Failed to load {inputs:?}: {error}")))?;
+
+    let power_of_two = dimensions.width.is_power_of_two() && dimensions.height.is_power_of_two();
+    if custom.is_some() && !power_of_two {
+        warnings.push(format!(
+            "{}x{} isn't a power of two, the texture has no mip levels",
+            dimensions.width, dimensions.height
+        ));
+    }
+
+    let loaded = image.metadata()?;
+    // Each texture takes its part of the mip chain, the SD one included
+    let passthrough =
+        loaded.format == format.dxgi_format && mips::layout_mismatch(&format, &loaded).is_none();
+    // Only DDS sources carry mip levels, and reading their header is cheap
+    let has_mips = !mip_files.is_empty()
+        || images
+            .iter()
+            .any(|file| dxtex::metadata(file).map_or(false, |metadata| metadata.mipLevels > 1));
+    let user_mips = if sliced || loaded.format == format.dxgi_format || !has_mips {
+        None
+    } else {
+        load_user_mips(
             format.dxgi_format,
-            dimensions.width,
-            dimensions.height,
-            format.array_size,
-            1,
-            &texture_data,
+            pixel_format,
+            dimensions,
+            &images,
+            &mip_files,
+            options,
         )
-        .log_failure_as("new_2d")
-    } {
-        Err(error) => return task_error(group, error, start.elapsed()),
-        Ok(dx) => dx,
+        .map_err(|error| Error::message(format!("Failed to load the mip levels: {error}")))?
     };
 
-    for file in group.outputs.as_ref().unwrap() {
-        // FIXME: quick hack to git er dun
-        if file.as_str().ends_with(".raw") {
-            let pixels = dx.pixels().unwrap();
-            std::fs::write(file, pixels)
-                .log_failure_with(|| format!("Trying to save {file}"))
-                .ignore();
-        } else if file.as_str().ends_with(".texture") {
-            use std::io::prelude::*;
-            let mut file = File::create(file)
-                .log_failure_with(|| format!("Trying to save {file}"))
-                .unwrap();
-            file.write_all(bytemuck::bytes_of(&texture_file::FileHeader::default()))
-                .unwrap();
-            file.write_all(bytemuck::bytes_of(&texture_file::TextureHeader::default()))
-                .unwrap();
-            file.write_all(texture_file::TEXTURE_TAG).unwrap();
-            file.write_all(bytemuck::bytes_of(&format.to_header()))
-                .unwrap();
-            file.write_all(&texture_data).unwrap();
+    for (dimensions, output_file) in format.dimensions_iter().zip(outputs.iter()) {
+        let metadata = image.metadata().log_failure()?;
+        let chain = match &user_mips {
+            Some(chain) => {
+                let sub_chain = mips::sub_chain(chain, dimensions)?;
+                if sub_chain.is_none() {
+                    warnings.push(format!(
+                        "The mip levels don't fit {}x{}, generated them instead",
+                        dimensions.width, dimensions.height
+                    ));
+                }
+                sub_chain
+            }
+            None => None,
+        };
+
+        let image = if passthrough {
+            Cow::Owned(mips::sub_chain(&image, dimensions)?.ok_or_else(|| {
+                Error::message("Internal error: the mip chain doesn't fit the texture")
+            })?)
+        } else if let Some(chain) = chain {
+            Cow::Owned(chain)
+        } else if (dimensions.width, dimensions.height) == (metadata.width, metadata.height) {
+            Cow::Borrowed(&image)
+        } else {
+            Cow::Owned(image.resize_with(
+                dimensions.width,
+                dimensions.height,
+                options.import.resize_filter.dxtex() | TEX_FILTER_FLAGS::srgb_for(metadata.format),
+            )?)
+        };
+
+        let image = if normal_map && metadata.format == normals::FLOAT_FORMAT {
+            Cow::Owned(normals::import(
+                &image,
+                format.dxgi_format.uncompressed_format(),
+                dimensions.mipmaps,
+                &options.normals,
+            )?)
+        } else if metadata.format != format.dxgi_format
+            && metadata.format != format.dxgi_format.uncompressed_format()
+        {
+            Cow::Owned(image.convert(
+                format.dxgi_format.uncompressed_format(),
+                TEX_FILTER_FLAGS::default(),
+            )?)
+        } else {
+            image
+        };
+
+        let image = if dimensions.mipmaps > 1 {
+            let metadata = image.metadata()?;
+            let expected =
+                dxtex::expected_size_array(metadata.format, dimensions, format.array_size);
+            if image.len() == expected {
+                image
+            } else {
+                let data = image.pixels()?;
+                let stripped = DXImage::with_dimensions(
+                    metadata.format,
+                    Dimensions {
+                        mipmaps: 1,
+                        ..dimensions
+                    },
+                    format.array_size,
+                    &data,
+                )
+                .log_failure()?;
+                Cow::Owned(
+                    stripped
+                        .generate_mipmaps_with(
+                            dimensions.mipmaps,
+                            TEX_FILTER_FLAGS::srgb_for(metadata.format),
+                        )
+                        .log_failure()?,
+                )
+            }
+        } else {
+            image
+        };
+
+        let metadata = image.metadata()?;
+        let image = if metadata.format == format.dxgi_format {
+            image
+        } else {
+            let compressed =
+                image.compress_with(format.dxgi_format, options.import.quality.dxtex())?;
+            if format.dxgi_format.is_compressed() {
+                let error = CompressionError::measure(&image, &compressed)?;
+                event!(INFO, "Compressed {output_file}: {error}");
+                if error.is_low_quality() {
+                    warnings.push(format!(
+                        "{output_file} lost a lot of detail when compressed: {error}"
+                    ));
+                } else if options.import.report_error {
+                    warnings.push(format!("Compressed {output_file}: {error}"));
+                }
+            }
+            Cow::Owned(compressed)
+        };
+
+        if image.len() != dimensions.data_size {
+            let metadata = image.metadata()?;
+            event!(ERROR, name="failed to hit target", ?metadata, ?dimensions, format = %format.dxgi_format.display(), len = %image.len());
+
+            return error_message(format!(
+                "Failed to hit the correct data size: expected {}, got {}",
+                dimensions.data_size,
+                image.len()
+            ));
+        }
+
+        let mut writer = BufWriter::new(File::create(output_file)?);
+        if output_file.as_str().ends_with(".custom.texture") {
+            let raw_headers = header
+                .or_else(|| registry().raw_header_for(&header_format))
+                .ok_or_else(|| {
+                    Error::message(format!(
+                        "Internal error: Missing the correct headers for format id {}",
+                        header_format.id()
+                    ))
+                })?;
+            let mut format_header = texture_file::FormatHeader::from_hexstring(raw_headers)?;
+            if custom.is_some() {
+                format_header.set_layout(&format);
+            }
+
+            event!(TRACE, "Writing .texture headers to {output_file}");
+            writer.write_all(bytemuck::bytes_of(&texture_file::FileHeader::with_length(
+                format.standard.data_size,
+            )))?;
+            writer.write_all(bytemuck::bytes_of(&texture_file::TextureHeader::new()))?;
+            writer.write_all(texture_file::TEXTURE_TAG)?;
+            writer.write_all(bytemuck::bytes_of(&format_header))?;
         }
+        let pixels = image.pixels()?;
+        writer.write_all(&pixels)?;
+
+        event!(TRACE, "Saved {output_file}");
     }
 
-    TaskResult::Result(group, start.elapsed())
+    Ok(warnings)
 }
@@ -0,0 +1,285 @@
+//! Cubemaps as a single image instead of six `#01`..`#06` files.
+//!
+//! Faces follow the D3D order +X, -X, +Y, -Y, +Z, -Z and orientation, with +Y
+//! up and +Z forward. The crosses put +Z in the middle with -X on the left,
+//! and the vertical cross has -Z upside down at the bottom. The work is done
+//! on `R32G32B32A32_FLOAT` pixels.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use windows::Win32::Graphics::Dxgi::Common::DXGI_FORMAT_R32G32B32A32_FLOAT;
+
+use crate::dxtex::DXImage;
+use crate::formats::ImageFormat;
+use crate::options::CubeLayout;
+use crate::prelude::*;
+
+pub const CUBE_FACES: usize = 6;
+const FLOAT_FORMAT: DXGI_FORMAT = DXGI_FORMAT_R32G32B32A32_FLOAT;
+
+type Pixel = [f32; 4];
+
+/// Whether `format` is a cubemap that should be converted as `layout`.
+#[must_use]
+pub fn uses_layout(format: &TextureFormat, layout: CubeLayout) -> bool {
+    format.array_size == CUBE_FACES && layout != CubeLayout::Faces
+}
+
+/// The file a cubemap exported as `output_file` is written to as a layout.
+#[must_use]
+pub fn layout_file(output_file: &Utf8Path, image_format: ImageFormat) -> Utf8PathBuf {
+    let ext = image_format.extensions_str().first().copied().unwrap_or("png");
+    output_file.with_extension(ext)
+}
+
+/// Lays the six faces of `faces` out as one float image.
+pub fn to_layout(faces: &DXImage, layout: CubeLayout) -> Result<DXImage> {
+    let metadata = faces.metadata()?;
+    if metadata.arraySize != CUBE_FACES || metadata.width != metadata.height {
+        return error_message("Only six square faces can be laid out as a cubemap");
+    }
+
+    let float = faces.to_format(FLOAT_FORMAT)?;
+    let faces = (0 .. CUBE_FACES)
+        .map(|face| float.image(face * metadata.mipLevels).map(|data| to_pixels(&data)))
+        .collect::<Result<Vec<_>>>()?;
+
+    let (width, height) = layout_size(layout, metadata.width);
+    let pixels = assemble(&faces, metadata.width, layout);
+    DXImage::new_2d(FLOAT_FORMAT, width, height, 1, 1, bytemuck::cast_slice(&pixels))
+}
+
+/// Cuts a layout image into a float image with six faces.
+pub fn from_layout(image: &DXImage, layout: CubeLayout) -> Result<DXImage> {
+    let metadata = image.metadata()?;
+    let size = face_size(layout, metadata.width, metadata.height)?;
+
+    let float = image.to_format(FLOAT_FORMAT)?;
+    let pixels = to_pixels(&float.image(0)?);
+    let faces = extract(&pixels, metadata.width, metadata.height, size, layout);
+
+    let buffer: Vec<f32> = faces.into_iter().flatten().flatten().collect();
+    DXImage::new_2d(FLOAT_FORMAT, size, size, CUBE_FACES, 1, bytemuck::cast_slice(&buffer))
+}
+
+fn to_pixels(data: &[u8]) -> Vec<Pixel> {
+    data.chunks_exact(16)
+        .map(|bytes| {
+            let mut pixel = [0.0; 4];
+            for (channel, value) in pixel.iter_mut().zip(bytes.chunks_exact(4)) {
+                *channel = f32::from_ne_bytes([value[0], value[1], value[2], value[3]]);
+            }
+            pixel
+        })
+        .collect()
+}
+
+/// The size of the layout image for faces of `size`.
+#[must_use]
+pub const fn layout_size(layout: CubeLayout, size: usize) -> (usize, usize) {
+    match layout {
+        CubeLayout::Faces => (size, size),
+        CubeLayout::HorizontalCross => (size * 4, size * 3),
+        CubeLayout::VerticalCross => (size * 3, size * 4),
+        CubeLayout::Strip => (size * 6, size),
+        CubeLayout::Equirect => (size * 4, size * 2),
+    }
+}
+
+/// The face size of a layout image, if it has the right proportions.
+pub fn face_size(layout: CubeLayout, width: usize, height: usize) -> Result<usize> {
+    let size = match layout {
+        CubeLayout::Faces => width,
+        CubeLayout::HorizontalCross | CubeLayout::Equirect => width / 4,
+        CubeLayout::VerticalCross => width / 3,
+        CubeLayout::Strip => width / 6,
+    };
+
+    if size > 0 && layout_size(layout, size) == (width, height) {
+        Ok(size)
+    } else {
+        error_message(format!("A {width}x{height} image isn't a {layout} cubemap layout"))
+    }
+}
+
+/// The column and row of a face in a grid layout, and whether it is upside
+/// down.
+#[must_use]
+const fn face_cell(layout: CubeLayout, face: usize) -> (usize, usize, bool) {
+    match (layout, face) {
+        (CubeLayout::Strip, face) => (face, 0, false),
+        (_, 0) => (2, 1, false),
+        (_, 1) => (0, 1, false),
+        (_, 2) => (1, 0, false),
+        (_, 3) => (1, 2, false),
+        (_, 4) => (1, 1, false),
+        (CubeLayout::VerticalCross, _) => (1, 3, true),
+        (_, _) => (3, 1, false),
+    }
+}
+
+fn assemble(faces: &[Vec<Pixel>], size: usize, layout: CubeLayout) -> Vec<Pixel> {
+    let (width, height) = layout_size(layout, size);
+    let mut pixels = vec![[0.0; 4]; width * height];
+
+    if layout == CubeLayout::Equirect {
+        for (index, pixel) in pixels.iter_mut().enumerate() {
+            let u = ((index % width) as f32 + 0.5) / width as f32;
+            let v = ((index / width) as f32 + 0.5) / height as f32;
+            let (face, s, t) = direction_face(equirect_direction(u, v));
+            *pixel = sample(&faces[face], size, size, s, t, false);
+        }
+        return pixels;
+    }
+
+    for (face, face_pixels) in faces.iter().enumerate() {
+        let (col, row, flipped) = face_cell(layout, face);
+        for y in 0 .. size {
+            for x in 0 .. size {
+                let (fx, fy) = if flipped {
+                    (size - 1 - x, size - 1 - y)
+                } else {
+                    (x, y)
+                };
+                pixels[(row * size + y) * width + col * size + x] = face_pixels[fy * size + fx];
+            }
+        }
+    }
+    pixels
+}
+
+fn extract(
+    pixels: &[Pixel],
+    width: usize,
+    height: usize,
+    size: usize,
+    layout: CubeLayout,
+) -> Vec<Vec<Pixel>> {
+    (0 .. CUBE_FACES)
+        .map(|face| {
+            let mut face_pixels = vec![[0.0; 4]; size * size];
+            for (index, pixel) in face_pixels.iter_mut().enumerate() {
+                let (x, y) = (index % size, index / size);
+                *pixel = if layout == CubeLayout::Equirect {
+                    let s = (x as f32 + 0.5) / size as f32;
+                    let t = (y as f32 + 0.5) / size as f32;
+                    let (u, v) = direction_equirect(face_direction(face, s, t));
+                    sample(pixels, width, height, u, v, true)
+                } else {
+                    let (col, row, flipped) = face_cell(layout, face);
+                    let (fx, fy) = if flipped {
+                        (size - 1 - x, size - 1 - y)
+                    } else {
+                        (x, y)
+                    };
+                    pixels[(row * size + fy) * width + col * size + fx]
+                };
+            }
+            face_pixels
+        })
+        .collect()
+}
+
+/// The direction through `s`, `t` (`0..1`, from the top left) on a face.
+#[must_use]
+pub fn face_direction(face: usize, s: f32, t: f32) -> [f32; 3] {
+    let (sc, tc) = (s * 2.0 - 1.0, t * 2.0 - 1.0);
+    match face {
+        0 => [1.0, -tc, -sc],
+        1 => [-1.0, -tc, sc],
+        2 => [sc, 1.0, tc],
+        3 => [sc, -1.0, -tc],
+        4 => [sc, -tc, 1.0],
+        _ => [-sc, -tc, -1.0],
+    }
+}
+
+/// The face a direction points at and where on it.
+#[must_use]
+pub fn direction_face([x, y, z]: [f32; 3]) -> (usize, f32, f32) {
+    let (ax, ay, az) = (x.abs(), y.abs(), z.abs());
+    let (face, sc, tc, ma) = if ax >= ay && ax >= az {
+        if x > 0.0 { (0, -z, -y, ax) } else { (1, z, -y, ax) }
+    } else if ay >= az {
+        if y > 0.0 { (2, x, z, ay) } else { (3, x, -z, ay) }
+    } else if z > 0.0 {
+        (4, x, -y, az)
+    } else {
+        (5, -x, -y, az)
+    };
+
+    (face, (sc / ma + 1.0) / 2.0, (tc / ma + 1.0) / 2.0)
+}
+
+fn equirect_direction(u: f32, v: f32) -> [f32; 3] {
+    use std::f32::consts::{FRAC_PI_2, PI};
+
+    let longitude = u * 2.0 * PI - PI;
+    let latitude = FRAC_PI_2 - v * PI;
+    [
+        latitude.cos() * longitude.sin(),
+        latitude.sin(),
+        latitude.cos() * longitude.cos(),
+    ]
+}
+
+fn direction_equirect([x, y, z]: [f32; 3]) -> (f32, f32) {
+    use std::f32::consts::{FRAC_PI_2, PI};
+
+    let length = (x * x + y * y + z * z).sqrt();
+    let longitude = x.atan2(z);
+    let latitude = (y / length).asin();
+    ((longitude + PI) / (2.0 * PI), (FRAC_PI_2 - latitude) / PI)
+}
+
+/// Bilinear sample at `u`, `v` (`0..1`), wrapping around horizontally for
+/// equirectangular images.
+fn sample(pixels: &[Pixel], width: usize, height: usize, u: f32, v: f32, wrap: bool) -> Pixel {
+    let x = u * width as f32 - 0.5;
+    let y = (v * height as f32 - 0.5).max(0.0).min((height - 1) as f32);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+
+    let column = |x: f32| {
+        if wrap {
+            (x as isize).rem_euclid(width as isize) as usize
+        } else {
+            x.max(0.0).min((width - 1) as f32) as usize
+        }
+    };
+    let (left, right) = (column(x0), column(x0 + 1.0));
+    let (top, bottom) = (y0 as usize, (y0 as usize + 1).min(height - 1));
+
+    let mut pixel = [0.0; 4];
+    for (channel, value) in pixel.iter_mut().enumerate() {
+        let upper = pixels[top * width + left][channel] * (1.0 - fx)
+            + pixels[top * width + right][channel] * fx;
+        let lower = pixels[bottom * width + left][channel] * (1.0 - fx)
+            + pixels[bottom * width + right][channel] * fx;
+        *value = upper * (1.0 - fy) + lower * fy;
+    }
+    pixel
+}
+
+#[test]
+fn test_cube_layouts() {
+    for face in 0 .. CUBE_FACES {
+        let (found, s, t) = direction_face(face_direction(face, 0.25, 0.75));
+        assert_eq!(found, face);
+        assert!((s - 0.25).abs() < 1e-5 && (t - 0.75).abs() < 1e-5);
+    }
+
+    let (u, v) = direction_equirect(equirect_direction(0.3, 0.6));
+    assert!((u - 0.3).abs() < 1e-5 && (v - 0.6).abs() < 1e-5);
+
+    let faces: Vec<Vec<Pixel>> = (0 .. CUBE_FACES)
+        .map(|face| (0 .. 4).map(|i| [face as f32, i as f32, 0.0, 1.0]).collect())
+        .collect();
+    for layout in [CubeLayout::HorizontalCross, CubeLayout::VerticalCross, CubeLayout::Strip] {
+        let (width, height) = layout_size(layout, 2);
+        assert_eq!(face_size(layout, width, height).unwrap(), 2);
+
+        let pixels = assemble(&faces, 2, layout);
+        assert_eq!(extract(&pixels, width, height, 2, layout), faces);
+    }
+    assert!(face_size(CubeLayout::HorizontalCross, 64, 64).is_err());
+}
@@ -27,10 +27,45 @@ pub struct TEX_FILTER_FLAGS(pub u32);
 impl TEX_FILTER_FLAGS {
     #[cfg_attr(not(feature = "disable-wic"), allow(dead_code))]
     pub const TEX_FILTER_FORCE_NON_WIC: Self = Self(0x1000_0000);
+    pub const TEX_FILTER_POINT: Self = Self(0x10_0000);
+    pub const TEX_FILTER_LINEAR: Self = Self(0x20_0000);
+    pub const TEX_FILTER_CUBIC: Self = Self(0x30_0000);
+    pub const TEX_FILTER_BOX: Self = Self(0x40_0000);
+    pub const TEX_FILTER_TRIANGLE: Self = Self(0x50_0000);
     pub const TEX_FILTER_SRGB: Self =
         Self(Self::TEX_FILTER_SRGB_IN.0 | Self::TEX_FILTER_SRGB_OUT.0);
     pub const TEX_FILTER_SRGB_IN: Self = Self(0x100_0000);
     pub const TEX_FILTER_SRGB_OUT: Self = Self(0x200_0000);
+
+    /// The sRGB flags for filtering an image in `format`.
+    #[must_use]
+    pub fn srgb_for(format: DXGI_FORMAT) -> Self {
+        if super::is_srgb(format) { Self::TEX_FILTER_SRGB } else { Self(0) }
+    }
+}
+
+impl std::ops::BitOr for TEX_FILTER_FLAGS {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self { Self(self.0 | other.0) }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct TEX_COMPRESS_FLAGS(pub u32);
+
+impl TEX_COMPRESS_FLAGS {
+    pub const TEX_COMPRESS_DITHER: Self = Self(0x3_0000);
+    pub const TEX_COMPRESS_UNIFORM: Self = Self(0x4_0000);
+    pub const TEX_COMPRESS_BC7_USE_3SUBSETS: Self = Self(0x8_0000);
+    pub const TEX_COMPRESS_BC7_QUICK: Self = Self(0x10_0000);
+    pub const TEX_COMPRESS_PARALLEL: Self = Self(0x1000_0000);
+}
+
+impl std::ops::BitOr for TEX_COMPRESS_FLAGS {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self { Self(self.0 | other.0) }
 }
 
 #[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
@@ -168,6 +203,18 @@ extern "C" {
     pub fn OverrideFormat(image: DXPtr, format: DXGI_FORMAT) -> HRESULT;
 }
 
+// Needs a DXTexWrapper.lib that exports it, the bundled one only has
+// V2_Compress
+#[cfg(feature = "compress-ex")]
+extern "C" {
+    pub fn V2_CompressEx(
+        image: DXPtr,
+        to_format: DXGI_FORMAT,
+        flags: TEX_COMPRESS_FLAGS,
+        dst: *mut DXImage,
+    ) -> HRESULT;
+}
+
 #[cfg(not(feature = "disable-wic"))]
 extern "C" {
     pub fn GetMetadataFromWICFile(
@@ -5,14 +5,18 @@ use std::mem::MaybeUninit;
 
 use camino::Utf8Path;
 use image::ImageFormat;
-use windows::Win32::Graphics::Dxgi::Common::{DXGI_FORMAT, DXGI_FORMAT_R8G8B8A8_UNORM};
+use windows::Win32::Graphics::Dxgi::Common::{
+    DXGI_FORMAT,
+    DXGI_FORMAT_R8G8B8A8_UNORM,
+    DXGI_FORMAT_R8G8B8A8_UNORM_SRGB,
+};
 
 use crate::util::{initialize_com, to_wstring};
 
 const WIC_FORMATS: &[ImageFormat] = &[ImageFormat::Bmp, ImageFormat::Gif, ImageFormat::Png];
 mod ffi;
 #[allow(clippy::wildcard_imports)] use ffi::*;
-pub use ffi::{TexMetadata, TEX_DIMENSION, TEX_FILTER_FLAGS};
+pub use ffi::{TexMetadata, TEX_COMPRESS_FLAGS, TEX_DIMENSION, TEX_FILTER_FLAGS};
 
 use crate::prelude::*;
 
@@ -145,12 +149,15 @@ impl DXImage {
 
     #[inline]
     pub fn generate_mipmaps(&self, mipmaps: u8) -> Result<Self> {
+        self.generate_mipmaps_with(mipmaps, TEX_FILTER_FLAGS::default())
+    }
+
+    #[inline]
+    pub fn generate_mipmaps_with(&self, mipmaps: u8, flags: TEX_FILTER_FLAGS) -> Result<Self> {
         let mut out = MaybeUninit::uninit();
 
-        #[cfg(not(feature = "disable-wic"))]
-        let flags = TEX_FILTER_FLAGS::default();
         #[cfg(feature = "disable-wic")]
-        let flags = TEX_FILTER_FLAGS::TEX_FILTER_FORCE_NON_WIC;
+        let flags = flags | TEX_FILTER_FLAGS::TEX_FILTER_FORCE_NON_WIC;
 
         Ok(unsafe {
             GenerateMipmaps(self.0, flags, usize::from(mipmaps), out.as_mut_ptr()).ok()?;
@@ -177,12 +184,42 @@ impl DXImage {
         })
     }
 
+    /// Compresses with DirectXTex's compression flags. Without the
+    /// `compress-ex` feature the flags are ignored.
+    #[inline]
+    pub fn compress_with(&self, to_format: DXGI_FORMAT, flags: TEX_COMPRESS_FLAGS) -> Result<Self> {
+        #[cfg(feature = "compress-ex")]
+        {
+            let mut out = MaybeUninit::uninit();
+
+            Ok(unsafe {
+                V2_CompressEx(self.0, to_format, flags, out.as_mut_ptr()).ok()?;
+                out.assume_init()
+            })
+        }
+        #[cfg(not(feature = "compress-ex"))]
+        {
+            let _ = flags;
+            self.compress(to_format)
+        }
+    }
+
     #[inline]
     pub fn resize(&self, width: usize, height: usize) -> Result<Self> {
+        self.resize_with(width, height, TEX_FILTER_FLAGS(0))
+    }
+
+    #[inline]
+    pub fn resize_with(
+        &self,
+        width: usize,
+        height: usize,
+        flags: TEX_FILTER_FLAGS,
+    ) -> Result<Self> {
         let mut out = MaybeUninit::uninit();
 
         Ok(unsafe {
-            Resize(self.0, width, height, TEX_FILTER_FLAGS(0), out.as_mut_ptr()).ok()?;
+            Resize(self.0, width, height, flags, out.as_mut_ptr()).ok()?;
             out.assume_init()
         })
     }
@@ -279,6 +316,7 @@ impl DXImage {
             ImageFormat::Tga => self.save_tga(array_index, file),
             ImageFormat::Hdr => self.save_hdr(array_index, file),
             ImageFormat::OpenExr => self.save_exr(array_index, file),
+            ImageFormat::WebP => self.save_webp(array_index, file),
             #[cfg(not(feature = "disable-wic"))]
             image_format if is_wic_format(image_format) => {
                 self.save_wic(array_index, image_format, file)
@@ -333,6 +371,28 @@ impl DXImage {
         Ok(())
     }
 
+    /// Saves one array slice as a lossless WebP file. WIC can't write WebP, so
+    /// it goes through image-rs with the stored values unchanged.
+    pub fn save_webp(&self, array_index: usize, file: impl AsRef<Utf8Path>) -> Result<()> {
+        let rgba = if is_srgb(self.metadata()?.format) {
+            DXGI_FORMAT_R8G8B8A8_UNORM_SRGB
+        } else {
+            DXGI_FORMAT_R8G8B8A8_UNORM
+        };
+        let image = self.to_format(rgba)?;
+        let metadata = image.metadata()?;
+        let pixels = image.image(array_index * metadata.mipLevels)?;
+
+        let writer = std::io::BufWriter::new(std::fs::File::create(file.as_ref())?);
+        image::codecs::webp::WebPEncoder::new_lossless(writer).encode(
+            &pixels,
+            metadata.width as u32,
+            metadata.height as u32,
+            image::ColorType::Rgba8,
+        )?;
+        Ok(())
+    }
+
     // TODO: Maybe pass ImageFormat
     #[cfg(not(feature = "disable-wic"))]
     pub fn save_wic(
@@ -389,7 +449,6 @@ impl DXImage {
         Ok(ret)
     }
 
-    // FIXME: currently only works for uncompressed
     pub fn to_format<'image>(&'image self, format: DXGI_FORMAT) -> Result<Cow<'image, Self>> {
         let mut metadata = self.metadata()?;
         let mut ret = Cow::Borrowed(self);
@@ -400,7 +459,7 @@ impl DXImage {
         }
 
         if metadata.format != format {
-            ret = Cow::Owned(self.convert(format, TEX_FILTER_FLAGS::default())?);
+            ret = Cow::Owned(ret.convert(format, TEX_FILTER_FLAGS::default())?);
         }
 
         Ok(ret)
@@ -515,6 +574,7 @@ pub fn load(file: impl AsRef<Utf8Path>) -> Result<DXImage> {
         Some(format) if format == ImageFormat::Tga => load_tga(file),
         Some(format) if format == ImageFormat::Hdr => load_hdr(file),
         Some(format) if format == ImageFormat::OpenExr => load_exr(file),
+        Some(format) if format == ImageFormat::WebP => load_webp(file),
         #[cfg(not(feature = "disable-wic"))]
         Some(format) if is_wic_format(format) => load_wic(file),
         _ => error_message("DirectXTex tried to open an unsupported file format"),
@@ -531,6 +591,20 @@ pub fn load_dds(file: impl AsRef<Utf8Path>) -> Result<DXImage> {
     })
 }
 
+/// Decodes a WebP file with image-rs, see [`DXImage::save_webp`].
+pub fn load_webp(file: impl AsRef<Utf8Path>) -> Result<DXImage> {
+    let image = image::open(file.as_ref())?.into_rgba8();
+
+    DXImage::new_2d(
+        DXGI_FORMAT_R8G8B8A8_UNORM,
+        image.width() as usize,
+        image.height() as usize,
+        1,
+        1,
+        image.as_raw(),
+    )
+}
+
 pub fn load_tga(file: impl AsRef<Utf8Path>) -> Result<DXImage> {
     let file = to_wstring(file);
     let mut handle = MaybeUninit::uninit();
@@ -586,6 +660,7 @@ pub fn metadata(file: impl AsRef<Utf8Path>) -> Result<TexMetadata> {
         Some(format) if format == ImageFormat::Tga => metadata_from_tga(file),
         Some(format) if format == ImageFormat::Hdr => metadata_from_hdr(file),
         Some(format) if format == ImageFormat::OpenExr => metadata_from_exr(file),
+        Some(format) if format == ImageFormat::WebP => metadata_from_webp(file),
         #[cfg(not(feature = "disable-wic"))]
         Some(format) if is_wic_format(format) => metadata_from_wic(file),
         format => error_message(format!(
@@ -622,6 +697,21 @@ pub fn metadata_from_exr(file: impl AsRef<Utf8Path>) -> Result<TexMetadata> {
     Ok(metadata)
 }
 
+pub fn metadata_from_webp(file: impl AsRef<Utf8Path>) -> Result<TexMetadata> {
+    let (width, height) = image::image_dimensions(file.as_ref())?;
+
+    Ok(TexMetadata {
+        width: width as usize,
+        height: height as usize,
+        depth: 1,
+        arraySize: 1,
+        mipLevels: 1,
+        format: DXGI_FORMAT_R8G8B8A8_UNORM,
+        dimension: TEX_DIMENSION::Texture2D,
+        ..TexMetadata::default()
+    })
+}
+
 pub fn metadata_from_wic(file: impl AsRef<Utf8Path>) -> Result<TexMetadata> {
     initialize_com()?;
 
@@ -0,0 +1,452 @@
+//! Texture format metadata stored inside exported images, so it survives
+//! the images being moved away from their metadata file.
+//!
+//! * PNG: a `tEXt` chunk with the keyword `SpiderTex`
+//! * TGA: the author comments of the TGA 2.0 extension area, with the
+//!   software id set to `SpiderTex`
+//! * EXR: a `spidertex` string attribute in the header of single part scan
+//!   line images
+//!
+//! The value is the JSON form of [`EmbeddedFormat`] in all three.
+
+use camino::Utf8Path;
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+
+pub const KEYWORD: &str = "SpiderTex";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmbeddedFormat {
+    pub id:     FormatId,
+    /// The raw format header of the source texture
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub header: Option<String>,
+}
+
+impl EmbeddedFormat {
+    /// The registry format for the id, or the format decoded from the header
+    /// if the registry doesn't know it.
+    #[must_use]
+    pub fn format(&self) -> Option<TextureFormat> {
+        registry().try_get(self.id).copied().or_else(|| {
+            let header = texture_file::FormatHeader::from_hexstring(self.header.as_ref()?)
+                .log_failure_as("Embedded format header")
+                .ok()?;
+            Some(header.to())
+        })
+    }
+}
+
+/// Whether images with this extension can carry an embedded format.
+#[must_use]
+pub fn supported(file: &Utf8Path) -> bool {
+    matches!(
+        file.extension().map(str::to_ascii_lowercase).as_deref(),
+        Some("png" | "tga" | "exr")
+    )
+}
+
+/// Stores `embedded` in an already saved image, replacing a previous one.
+pub fn write(file: &Utf8Path, embedded: &EmbeddedFormat) -> Result<()> {
+    let value = serde_json::to_string(embedded)?;
+    let data = std::fs::read(file)?;
+
+    let data = match file.extension().map(str::to_ascii_lowercase).as_deref() {
+        Some("png") => png::write(data, &value)?,
+        Some("tga") => tga::write(data, &value)?,
+        Some("exr") => exr::write(data, &value)?,
+        _ => return error_message(format!("Can't embed the format in {file}")),
+    };
+
+    std::fs::write(file, data)?;
+    event!(TRACE, "Embedded {} in {file}", embedded.id);
+
+    Ok(())
+}
+
+/// Reads the embedded format of an image, if it has one.
+#[must_use]
+pub fn read(file: &Utf8Path) -> Option<EmbeddedFormat> {
+    if !supported(file) {
+        return None;
+    }
+
+    let data = std::fs::read(file).ok()?;
+    let value = match file.extension().map(str::to_ascii_lowercase).as_deref() {
+        Some("png") => png::read(&data),
+        Some("tga") => tga::read(&data),
+        Some("exr") => exr::read(&data),
+        _ => None,
+    }?;
+
+    serde_json::from_str(&value)
+        .log_failure_with(|| format!("Failed to read the embedded format of {file}"))
+        .ok()
+}
+
+fn malformed<T>(kind: &str) -> Result<T> { error_message(format!("Malformed {kind} file")) }
+
+mod png {
+    use super::{malformed, KEYWORD};
+    use crate::prelude::*;
+
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+    struct Chunk<'a> {
+        kind:  &'a [u8],
+        data:  &'a [u8],
+        /// The whole chunk, including length and CRC
+        bytes: &'a [u8],
+    }
+
+    fn chunks(data: &[u8]) -> impl Iterator<Item = Chunk<'_>> {
+        let mut pos = SIGNATURE.len();
+        std::iter::from_fn(move || {
+            let len = u32::from_be_bytes(data.get(pos .. pos + 4)?.try_into().ok()?) as usize;
+            let end = pos + 12 + len;
+            let bytes = data.get(pos .. end)?;
+            pos = end;
+
+            Some(Chunk {
+                kind: &bytes[4 .. 8],
+                data: &bytes[8 .. 8 + len],
+                bytes,
+            })
+        })
+    }
+
+    fn text<'a>(chunk: &'a Chunk<'a>) -> Option<&'a str> {
+        if chunk.kind != b"tEXt" {
+            return None;
+        }
+        let separator = chunk.data.iter().position(|b| *b == 0)?;
+        if &chunk.data[.. separator] != KEYWORD.as_bytes() {
+            return None;
+        }
+        std::str::from_utf8(&chunk.data[separator + 1 ..]).ok()
+    }
+
+    pub fn read(data: &[u8]) -> Option<String> {
+        if !data.starts_with(SIGNATURE) {
+            return None;
+        }
+        chunks(data).find_map(|chunk| text(&chunk).map(ToOwned::to_owned))
+    }
+
+    pub fn write(data: Vec<u8>, value: &str) -> Result<Vec<u8>> {
+        if !data.starts_with(SIGNATURE) {
+            return malformed("PNG");
+        }
+
+        let mut body = Vec::with_capacity(KEYWORD.len() + 1 + value.len());
+        body.extend_from_slice(KEYWORD.as_bytes());
+        body.push(0);
+        body.extend_from_slice(value.as_bytes());
+
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(b"tEXt");
+        hasher.update(&body);
+        let crc = hasher.finalize();
+
+        let mut output = Vec::with_capacity(data.len() + body.len() + 12);
+        output.extend_from_slice(SIGNATURE);
+
+        let mut written = false;
+        for chunk in chunks(&data) {
+            if text(&chunk).is_some() {
+                continue;
+            }
+            output.extend_from_slice(chunk.bytes);
+
+            // Right after the header, so it's found without reading the pixels
+            if chunk.kind == b"IHDR" {
+                output.extend_from_slice(&(body.len() as u32).to_be_bytes());
+                output.extend_from_slice(b"tEXt");
+                output.extend_from_slice(&body);
+                output.extend_from_slice(&crc.to_be_bytes());
+                written = true;
+            }
+        }
+
+        if written {
+            Ok(output)
+        } else {
+            malformed("PNG")
+        }
+    }
+}
+
+mod tga {
+    use super::{malformed, KEYWORD};
+    use crate::prelude::*;
+
+    const SIGNATURE: &[u8] = b"TRUEVISION-XFILE.\0";
+    const FOOTER_SIZE: usize = 26;
+    const EXTENSION_SIZE: usize = 495;
+    const COMMENTS: std::ops::Range<usize> = 43 .. 367;
+    const COMMENT_LINE: usize = 81;
+    const SOFTWARE_ID: std::ops::Range<usize> = 426 .. 467;
+
+    /// The extension and developer area offsets from a TGA 2.0 footer.
+    fn footer(data: &[u8]) -> Option<(usize, u32)> {
+        let footer = data.get(data.len().checked_sub(FOOTER_SIZE)? ..)?;
+        if &footer[8 ..] != SIGNATURE {
+            return None;
+        }
+        let extension = u32::from_le_bytes(footer[0 .. 4].try_into().ok()?);
+        let developer = u32::from_le_bytes(footer[4 .. 8].try_into().ok()?);
+        Some((extension as usize, developer))
+    }
+
+    fn extension_area(data: &[u8]) -> Option<std::ops::Range<usize>> {
+        let (offset, _) = footer(data)?;
+        let range = offset .. offset + EXTENSION_SIZE;
+        (offset != 0 && range.end <= data.len() - FOOTER_SIZE).then_some(range)
+    }
+
+    pub fn read(data: &[u8]) -> Option<String> {
+        let area = &data[extension_area(data)?];
+        if !area[SOFTWARE_ID].starts_with(KEYWORD.as_bytes()) {
+            return None;
+        }
+
+        let value: Vec<u8> = area[COMMENTS]
+            .chunks(COMMENT_LINE)
+            .flat_map(|line| line.iter().take_while(|b| **b != 0).copied())
+            .collect();
+        String::from_utf8(value).ok()
+    }
+
+    pub fn write(mut data: Vec<u8>, value: &str) -> Result<Vec<u8>> {
+        let lines = COMMENTS.len() / COMMENT_LINE;
+        if value.len() > lines * (COMMENT_LINE - 1) {
+            return error_message("The format is too long to store in a TGA file");
+        }
+
+        let area = match extension_area(&data) {
+            Some(area) => area,
+            None => {
+                let developer = match footer(&data) {
+                    Some((_, developer)) => {
+                        data.truncate(data.len() - FOOTER_SIZE);
+                        developer
+                    }
+                    None => 0,
+                };
+                if data.len() < 18 {
+                    return malformed("TGA");
+                }
+
+                let offset = data.len();
+                let mut area = vec![0_u8; EXTENSION_SIZE];
+                area[0 .. 2].copy_from_slice(&(EXTENSION_SIZE as u16).to_le_bytes());
+                data.extend_from_slice(&area);
+                data.extend_from_slice(&(offset as u32).to_le_bytes());
+                data.extend_from_slice(&developer.to_le_bytes());
+                data.extend_from_slice(SIGNATURE);
+
+                offset .. offset + EXTENSION_SIZE
+            }
+        };
+
+        let area = &mut data[area];
+        area[COMMENTS].fill(0);
+        for (line, text) in area[COMMENTS]
+            .chunks_mut(COMMENT_LINE)
+            .zip(value.as_bytes().chunks(COMMENT_LINE - 1))
+        {
+            line[.. text.len()].copy_from_slice(text);
+        }
+        area[SOFTWARE_ID].fill(0);
+        area[SOFTWARE_ID.start .. SOFTWARE_ID.start + KEYWORD.len()]
+            .copy_from_slice(KEYWORD.as_bytes());
+
+        Ok(data)
+    }
+}
+
+mod exr {
+    use super::malformed;
+    use crate::prelude::*;
+
+    const MAGIC: &[u8] = &[0x76, 0x2f, 0x31, 0x01];
+    const ATTRIBUTE: &str = "spidertex";
+    /// Tiled, deep or multi-part images, which have their own offset tables
+    const UNSUPPORTED_FLAGS: u32 = 0x200 | 0x800 | 0x1000;
+
+    struct Attribute<'a> {
+        name:  &'a str,
+        kind:  &'a str,
+        value: &'a [u8],
+        /// The whole attribute, including name, type and size
+        bytes: &'a [u8],
+    }
+
+    fn c_string(data: &[u8], pos: usize) -> Option<(&str, usize)> {
+        let len = data.get(pos ..)?.iter().position(|b| *b == 0)?;
+        let string = std::str::from_utf8(&data[pos .. pos + len]).ok()?;
+        Some((string, pos + len + 1))
+    }
+
+    /// The header attributes and the position of the offset table.
+    fn header(data: &[u8]) -> Option<(Vec<Attribute<'_>>, usize)> {
+        if !data.starts_with(MAGIC) {
+            return None;
+        }
+        let flags = u32::from_le_bytes(data.get(4 .. 8)?.try_into().ok()?);
+        if flags & UNSUPPORTED_FLAGS != 0 {
+            return None;
+        }
+
+        let mut attributes = Vec::new();
+        let mut pos = 8;
+        loop {
+            let start = pos;
+            let (name, next) = c_string(data, pos)?;
+            if name.is_empty() {
+                return Some((attributes, next));
+            }
+            let (kind, next) = c_string(data, next)?;
+            let size = i32::from_le_bytes(data.get(next .. next + 4)?.try_into().ok()?);
+            let value_start = next + 4;
+            pos = value_start + usize::try_from(size).ok()?;
+
+            attributes.push(Attribute {
+                name,
+                kind,
+                value: data.get(value_start .. pos)?,
+                bytes: &data[start .. pos],
+            });
+        }
+    }
+
+    fn i32_at(value: &[u8], index: usize) -> Option<i32> {
+        Some(i32::from_le_bytes(
+            value.get(index * 4 .. index * 4 + 4)?.try_into().ok()?,
+        ))
+    }
+
+    /// The number of entries in the offset table of a scan line image.
+    fn chunk_count(attributes: &[Attribute<'_>]) -> Option<usize> {
+        let find = |name: &str| attributes.iter().find(|a| a.name == name);
+
+        let window = find("dataWindow")?.value;
+        let height = i32_at(window, 3)? - i32_at(window, 1)? + 1;
+        let lines_per_chunk = match find("compression")?.value.first()? {
+            0 | 1 | 2 => 1,
+            3 | 5 => 16,
+            4 | 6 | 7 | 8 => 32,
+            9 => 256,
+            _ => return None,
+        };
+
+        let height = usize::try_from(height).ok()?;
+        Some((height + lines_per_chunk - 1) / lines_per_chunk)
+    }
+
+    pub fn read(data: &[u8]) -> Option<String> {
+        let (attributes, _) = header(data)?;
+        let attribute = attributes
+            .iter()
+            .find(|a| a.name == ATTRIBUTE && a.kind == "string")?;
+        std::str::from_utf8(attribute.value).ok().map(ToOwned::to_owned)
+    }
+
+    pub fn write(data: Vec<u8>, value: &str) -> Result<Vec<u8>> {
+        let (attributes, table) = match header(&data) {
+            Some(header) => header,
+            None => return malformed("single part scan line EXR"),
+        };
+        let chunks = match chunk_count(&attributes) {
+            Some(chunks) => chunks,
+            None => return malformed("EXR"),
+        };
+        let table_end = table + chunks * 8;
+        if table_end > data.len() {
+            return malformed("EXR");
+        }
+
+        let mut output = Vec::with_capacity(data.len() + value.len() + 32);
+        output.extend_from_slice(&data[.. 8]);
+        for attribute in attributes.iter().filter(|a| a.name != ATTRIBUTE) {
+            output.extend_from_slice(attribute.bytes);
+        }
+        output.extend_from_slice(ATTRIBUTE.as_bytes());
+        output.push(0);
+        output.extend_from_slice(b"string\0");
+        output.extend_from_slice(&(value.len() as i32).to_le_bytes());
+        output.extend_from_slice(value.as_bytes());
+        output.push(0);
+
+        // Chunk offsets are absolute, so they move with the header
+        let shift = output.len() as i64 - table as i64;
+        for offset in data[table .. table_end].chunks_exact(8) {
+            let offset = u64::from_le_bytes(offset.try_into().map_err(|_| Error::Internal)?);
+            output.extend_from_slice(&((offset as i64 + shift) as u64).to_le_bytes());
+        }
+        output.extend_from_slice(&data[table_end ..]);
+
+        Ok(output)
+    }
+}
+
+#[test]
+fn test_embed_roundtrip() {
+    let value = r#"{"id":"0200000000000000000000000400040062"}"#;
+
+    let mut png_file = b"\x89PNG\r\n\x1a\n".to_vec();
+    png_file.extend_from_slice(&13_u32.to_be_bytes());
+    png_file.extend_from_slice(b"IHDR");
+    png_file.extend_from_slice(&[0; 13 + 4]);
+    png_file.extend_from_slice(&0_u32.to_be_bytes());
+    png_file.extend_from_slice(b"IEND");
+    png_file.extend_from_slice(&[0; 4]);
+
+    let png_file = png::write(png_file, value).unwrap();
+    assert_eq!(png::read(&png_file).as_deref(), Some(value));
+    let png_file = png::write(png_file, "{}").unwrap();
+    assert_eq!(png::read(&png_file).as_deref(), Some("{}"));
+
+    let tga_file = tga::write(vec![0; 18], value).unwrap();
+    assert_eq!(tga::read(&tga_file).as_deref(), Some(value));
+    let tga_file = tga::write(tga_file, "{}").unwrap();
+    assert_eq!(tga::read(&tga_file).as_deref(), Some("{}"));
+    assert_eq!(tga_file.len(), 18 + 495 + 26);
+
+    let mut exr_file = vec![0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0];
+    exr_file.extend_from_slice(b"compression\0compression\0");
+    exr_file.extend_from_slice(&1_i32.to_le_bytes());
+    exr_file.push(0);
+    exr_file.extend_from_slice(b"dataWindow\0box2i\0");
+    exr_file.extend_from_slice(&16_i32.to_le_bytes());
+    for coordinate in [0_i32, 0, 3, 1] {
+        exr_file.extend_from_slice(&coordinate.to_le_bytes());
+    }
+    exr_file.push(0);
+    let table = exr_file.len();
+    let offsets = [table as u64 + 16, table as u64 + 24];
+    for offset in offsets {
+        exr_file.extend_from_slice(&offset.to_le_bytes());
+    }
+    exr_file.extend_from_slice(&[0; 16]);
+
+    let exr_file = exr::write(exr_file, value).unwrap();
+    assert_eq!(exr::read(&exr_file).as_deref(), Some(value));
+    let growth = "spidertex\0string\0".len() + 4 + value.len();
+    let table_bytes = &exr_file[table + growth .. table + growth + 16];
+    for (offset, bytes) in offsets.iter().zip(table_bytes.chunks_exact(8)) {
+        let shifted = u64::from_le_bytes(bytes.try_into().unwrap());
+        assert_eq!(shifted, offset + growth as u64);
+    }
+    let exr_file = exr::write(exr_file, "{}").unwrap();
+    assert_eq!(exr::read(&exr_file).as_deref(), Some("{}"));
+
+    // Tiled and multi-part files have offset tables this doesn't handle
+    for flags in [0x200_u32, 0x1000] {
+        let mut unsupported = exr_file.clone();
+        unsupported[4 .. 8].copy_from_slice(&(2 | flags).to_le_bytes());
+        assert!(exr::read(&unsupported).is_none());
+        assert!(exr::write(unsupported, value).is_err());
+    }
+}
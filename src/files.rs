@@ -3,9 +3,14 @@ use std::collections::{BTreeSet, HashSet};
 
 use camino::{Utf8Path, Utf8PathBuf};
 
-use crate::formats::ImageFormat;
+use crate::embed;
+use crate::formats::{sniff, ImageFormat, Provenance, SniffReport};
 use crate::images::{DxImport, Image, Warnings};
+use crate::lut::{CubeLut, CUBE_EXTENSION};
+use crate::mips;
 use crate::prelude::*;
+use crate::sidecar::{sidecar_file, Sidecar};
+use crate::META_EXTENSION;
 
 const GROUP_SEP: char = '#';
 
@@ -27,9 +32,10 @@ pub fn base_name(file: &Utf8Path) -> &str {
     //     .rfind('.')
     //     .map_or(file.as_str(), |pos| &file.as_str()[.. pos]);
 
-    let without_seps = without_ext
+    let without_mip = crate::mips::strip_mip_tag(without_ext);
+    let without_seps = without_mip
         .rfind(|c| c == GROUP_SEP)
-        .map_or(without_ext, |sep| &without_ext[.. sep]);
+        .map_or(without_mip, |sep| &without_mip[.. sep]);
     // let without_custom = without_seps.strip_suffix(".custom").or_else(|| without_seps.strip_suffix(".customhd")).unwrap_or(without_seps);
     // let without_suffix = without_custom.strip_suffix("_hd").unwrap_or(without_custom);
     let without_suffix = without_seps.strip_suffix("_hd").unwrap_or(without_seps);
@@ -50,11 +56,11 @@ pub fn base_name(file: &Utf8Path) -> &str {
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum FileFormat {
-    Ready(TextureFormat),
-    UserOverride(FormatId),
-    MetaOverride(TextureFormat),
-    FromHeader(TextureFormat),
-    FromSize(Vec<FormatId>),
+    Ready(TextureFormat, Provenance),
+    UserOverride(FormatId, Provenance),
+    MetaOverride(TextureFormat, Provenance),
+    FromHeader(TextureFormat, Provenance),
+    FromSize(Vec<FormatId>, Provenance),
     Unknown,
 }
 
@@ -115,16 +121,18 @@ impl From<Vec<Utf8PathBuf>> for FileStatus {
 pub enum FileType {
     Texture,
     Image(ImageFormat),
+    /// A colour grading LUT as a `.cube` file
+    Lut,
 }
 
 impl Ord for FileType {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         match (self, other) {
-            (Self::Texture, Self::Texture) | (Self::Image(_), Self::Image(_)) => {
-                std::cmp::Ordering::Equal
-            }
-            (_, Self::Image(_)) => std::cmp::Ordering::Less,
-            (_, Self::Texture) => std::cmp::Ordering::Greater,
+            (Self::Texture, Self::Texture)
+            | (Self::Image(_), Self::Image(_))
+            | (Self::Lut, Self::Lut) => std::cmp::Ordering::Equal,
+            (Self::Texture, _) | (Self::Image(_), Self::Lut) => std::cmp::Ordering::Less,
+            (_, Self::Texture | Self::Image(_)) => std::cmp::Ordering::Greater,
         }
     }
 }
@@ -142,6 +150,7 @@ impl TryFrom<&Utf8Path> for FileType {
     fn try_from(value: &Utf8Path) -> Result<Self, Self::Error> {
         match value.extension() {
             Some(ext) if is_texture_ext(ext) => Ok(Self::Texture),
+            Some(ext) if ext.eq_ignore_ascii_case(CUBE_EXTENSION) => Ok(Self::Lut),
             Some(ext) => {
                 if let Some(format) = ImageFormat::from_extension(ext) {
                     Ok(format.into())
@@ -220,7 +229,7 @@ impl FileGroupInfo for Scanned {
             OutputFormat::Exact { ref outputs, .. } => {
                 FileStatus::Ok(Warnings::new(), outputs.clone())
             }
-            OutputFormat::Candidates(_) => {
+            OutputFormat::Candidates(..) => {
                 FileStatus::Error("Multiple potential texture formats found".to_string())
             }
             OutputFormat::Unknown => {
@@ -249,18 +258,32 @@ pub struct Scanned {
     pub file_type: FileType,
     pub input:     FileStatus,
     pub output:    OutputFormat,
+    /// How the format was picked when several formats matched the file size
+    pub sniff:     Option<SniffReport>,
 }
 
 #[derive(Debug)]
 pub enum OutputFormat {
     Exact {
-        format:  TextureFormat,
-        outputs: Vec<Utf8PathBuf>,
+        format:     TextureFormat,
+        outputs:    Vec<Utf8PathBuf>,
+        provenance: Provenance,
     },
-    Candidates(Vec<TextureFormat>),
+    Candidates(Vec<TextureFormat>, Provenance),
     Unknown,
 }
 
+impl OutputFormat {
+    #[inline]
+    #[must_use]
+    pub const fn provenance(&self) -> Option<&Provenance> {
+        match self {
+            Self::Exact { provenance, .. } | Self::Candidates(_, provenance) => Some(provenance),
+            Self::Unknown => None,
+        }
+    }
+}
+
 impl FileGroup<Categorized> {
     #[must_use]
     pub fn scan(self) -> FileGroup<Scanned> {
@@ -269,36 +292,82 @@ impl FileGroup<Categorized> {
         match file_type {
             FileType::Texture => Self::scan_textures(files),
             FileType::Image(image_format) => Self::scan_images(image_format, files),
+            FileType::Lut => Self::scan_luts(files),
         }
     }
 
     #[must_use]
     pub fn scan_textures(files: Vec<Utf8PathBuf>) -> FileGroup<Scanned> {
         let mut formats = HashSet::new();
+        let mut provenance = Provenance::default();
 
         let input = FileStatus::from(|| {
             for texture_file in &files {
-                if let Some(texture_format) = ng_format_for_texture_file(texture_file) {
+                if let Some((texture_format, from)) = detect_texture_file_format(texture_file) {
                     formats.insert(texture_format);
+                    provenance = from;
                 }
             }
             if formats.is_empty() {
-                let sizes: Vec<usize> = files
+                if let Some((file, (pattern, id))) = files
                     .iter()
-                    .filter_map(|file| std::fs::metadata(file).map(|m| m.len() as usize).ok())
-                    .collect();
-                formats.extend(registry::formats_for_sizes(&sizes));
+                    .find_map(|file| Some((file, registry().override_for(file)?)))
+                {
+                    formats.insert(*registry::get(id));
+                    provenance = Provenance::from_pattern(file, pattern);
+                }
+            }
+            if formats.is_empty() {
+                for file in &files {
+                    if let Ok(size) = std::fs::metadata(file).map(|m| m.len() as usize) {
+                        let found = registry::formats_for_size(size);
+                        if !found.is_empty() && formats.is_empty() {
+                            provenance = Provenance::from_size(file, size, found.len());
+                        }
+                        formats.extend(found);
+                    }
+                }
             }
             Ok::<_, Error>(files)
         });
 
+        let mut sniffed = None;
+
         let output = if formats.len() > 1 {
-            OutputFormat::Candidates(formats.into_iter().collect())
+            let candidates: Vec<TextureFormat> = formats.into_iter().collect();
+            let report = sniff_inputs(&input, &candidates);
+
+            let output = match (&report, &input) {
+                (Some(SniffReport { winner: Some(format), scores }), FileStatus::Ok(_, inputs)) => {
+                    let detail = scores
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join("\n  ");
+                    OutputFormat::Exact {
+                        format:     *format,
+                        outputs:    as_images(format, inputs),
+                        provenance: provenance.sniffed(detail),
+                    }
+                }
+                (Some(report), _) => OutputFormat::Candidates(
+                    report.scores.iter().map(|score| score.format).collect(),
+                    provenance,
+                ),
+                (None, _) => OutputFormat::Candidates(candidates, provenance),
+            };
+            sniffed = report;
+
+            output
         } else if let (Some(format), FileStatus::Ok(_, inputs)) =
             (formats.into_iter().next(), &input)
         {
             let outputs = as_images(&format, inputs);
-            OutputFormat::Exact { format, outputs }
+            OutputFormat::Exact {
+                format,
+                outputs,
+                provenance,
+            }
         } else {
             OutputFormat::Unknown
         };
@@ -307,22 +376,34 @@ impl FileGroup<Categorized> {
             file_type: FileType::Texture,
             input,
             output,
+            sniff: sniffed,
         })
     }
 
     #[must_use]
     pub fn scan_images(image_format: ImageFormat, files: Vec<Utf8PathBuf>) -> FileGroup<Scanned> {
-        let mut texture_formats: HashSet<TextureFormat> = files
+        // Mip level files only matter when converting
+        let (images, _) = mips::partition(&files);
+
+        let mut provenance = Provenance::default();
+        let mut texture_formats: HashSet<TextureFormat> = images
             .iter()
-            .filter_map(|image_file| ng_format_for_image_file(image_file))
+            .filter_map(|image_file| detect_image_file_format(image_file))
+            .map(|(format, from)| {
+                provenance = from;
+                format
+            })
             .collect();
 
         if texture_formats.is_empty() {
-            let sizes: Vec<usize> = files
+            let sizes: Vec<usize> = images
                 .iter()
                 .filter_map(|file| std::fs::metadata(file).map(|m| m.len() as usize).ok())
                 .collect();
             texture_formats.extend(registry::formats_for_sizes(&sizes));
+            if let (Some(file), Some(size)) = (images.first(), sizes.first()) {
+                provenance = Provenance::from_size(file, *size, texture_formats.len());
+            }
         }
 
         let exact_format = if texture_formats.len() == 1 {
@@ -333,7 +414,7 @@ impl FileGroup<Categorized> {
 
         let input = FileStatus::from(|| {
             let warnings: Warnings = if let Some(texture_format) = exact_format {
-                files
+                images
                     .iter()
                     .map(|image_file| DxImport::quick_check(texture_format, image_file))
                     .collect::<Result<_>>()?
@@ -349,9 +430,10 @@ impl FileGroup<Categorized> {
                 OutputFormat::Exact {
                     format: *texture_format,
                     outputs,
+                    provenance,
                 }
             } else if texture_formats.len() > 1 {
-                OutputFormat::Candidates(texture_formats.into_iter().collect())
+                OutputFormat::Candidates(texture_formats.into_iter().collect(), provenance)
             } else {
                 OutputFormat::Unknown
             };
@@ -360,19 +442,105 @@ impl FileGroup<Categorized> {
             file_type: FileType::Image(image_format),
             input,
             output,
+            sniff: None,
         })
     }
+
+    #[must_use]
+    pub fn scan_luts(files: Vec<Utf8PathBuf>) -> FileGroup<Scanned> {
+        let mut provenance = Provenance::default();
+        let mut formats: HashSet<TextureFormat> = files
+            .iter()
+            .filter_map(|file| detect_image_file_format(file))
+            .map(|(format, from)| {
+                provenance = from;
+                format
+            })
+            .collect();
+
+        if formats.is_empty() {
+            if let Some((file, (pattern, id))) = files
+                .iter()
+                .find_map(|file| Some((file, registry().override_for(file)?)))
+            {
+                formats.insert(*registry::get(id));
+                provenance = Provenance::from_pattern(file, pattern);
+            }
+        }
+
+        let exact_format = if formats.len() == 1 {
+            formats.iter().next()
+        } else {
+            None
+        };
+
+        let input = FileStatus::from(|| {
+            let lut = match files.as_slice() {
+                [file] => CubeLut::load(file)?,
+                _ => return error_message("Only one .cube file can be imported per texture"),
+            };
+
+            let mut warnings = Warnings::new();
+            if let Some(format) = exact_format {
+                if !format.is_lut() {
+                    return error_message(format!("{format} isn't a LUT"));
+                }
+                if lut.size != format.standard.width {
+                    warnings.push(format!(
+                        "The LUT has {} entries per channel and will be resampled to {}",
+                        lut.size, format.standard.width
+                    ));
+                }
+            }
+            Ok::<_, Error>((warnings, files))
+        });
+
+        let output = if let (Some(format), FileStatus::Ok(_, inputs)) = (exact_format, &input) {
+            OutputFormat::Exact {
+                format: *format,
+                outputs: as_textures(format, inputs),
+                provenance,
+            }
+        } else if formats.len() > 1 {
+            OutputFormat::Candidates(formats.into_iter().collect(), provenance)
+        } else {
+            OutputFormat::Unknown
+        };
+
+        FileGroup(Scanned {
+            file_type: FileType::Lut,
+            input,
+            output,
+            sniff: None,
+        })
+    }
+}
+
+/// Trial-decodes the first readable input under every candidate format.
+fn sniff_inputs(input: &FileStatus, candidates: &[TextureFormat]) -> Option<SniffReport> {
+    let inputs = match input {
+        FileStatus::Ok(_, inputs) => inputs,
+        _ => return None,
+    };
+    let data = inputs
+        .iter()
+        .find_map(|file| std::fs::read(file).log_failure().ok())?;
+
+    let report = sniff(&data, candidates);
+    for score in &report.scores {
+        event!(DEBUG, "Sniffed {score}");
+    }
+    if let Some(winner) = &report.winner {
+        event!(INFO, "{winner} looks like the best of {} candidates", candidates.len());
+    }
+
+    Some(report)
 }
 
 #[must_use]
 #[cfg_attr(feature = "debug-inputs", instrument(ret))]
 pub fn ng_format_for_image_file(image_file: &Utf8Path) -> Option<TextureFormat> {
-    let file = Utf8PathBuf::from(base_name(image_file));
-
-    try_read_meta(&file)
-        .or_else(|| ng_format_for_texture_file(&file.with_extension("texture")))
-        .or_else(|| ng_format_for_texture_file(&file.with_extension("raw")))
-        .log_failure_with(|| format!("Failed to detect texture format of {image_file}"))
+    detect_image_file_format(image_file).map(|(format, _)| format)
 }
 
 #[must_use]
@@ -381,17 +549,44 @@ pub fn ng_format_for_image_file(image_file: &Utf8Path) -> Option<TextureFormat>
     instrument(ret)
 )]
 pub fn ng_format_for_texture_file(texture_file: &Utf8Path) -> Option<TextureFormat> {
+    detect_texture_file_format(texture_file).map(|(format, _)| format)
+}
+
+/// The format of an image file from its metadata file or the header of the
+/// texture next to it.
+#[must_use]
+pub fn detect_image_file_format(image_file: &Utf8Path) -> Option<(TextureFormat, Provenance)> {
+    let file = Utf8PathBuf::from(base_name(image_file));
+
+    try_read_embedded(image_file)
+        .or_else(|| try_read_meta(&file))
+        .or_else(|| detect_texture_file_format(&file.with_extension("texture")))
+        .or_else(|| detect_texture_file_format(&file.with_extension("raw")))
+        .log_failure_with(|| format!("Failed to detect texture format of {image_file}"))
+}
+
+#[must_use]
+pub fn detect_texture_file_format(texture_file: &Utf8Path) -> Option<(TextureFormat, Provenance)> {
     // FIXME
     let texture_file = texture_file.with_extension("texture");
 
-        if texture_file.exists() {
-            texture_file::read_header(&texture_file)
-                .log_failure_with(|| format!("Failed to read header of {texture_file}"))
-                .ok()
-                .and_then(|(header, _)| header.map(|header| header.to()))
-        } else {
-            None
-        }
+    if texture_file.exists() {
+        texture_file::read_header(&texture_file)
+            .log_failure_with(|| format!("Failed to read header of {texture_file}"))
+            .ok()
+            .and_then(|(header, _)| {
+                header.map(|header| {
+                    let provenance = Provenance::from_header(&texture_file, &header);
+                    let format = TextureFormat {
+                        source: provenance.source,
+                        ..header.to()
+                    };
+                    (format, provenance)
+                })
+            })
+    } else {
+        None
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -432,11 +627,24 @@ impl FileFormat {
     #[must_use]
     pub fn exact(&self) -> Option<&TextureFormat> {
         match self {
-            FileFormat::Ready(format)
-            | FileFormat::FromHeader(format)
-            | FileFormat::MetaOverride(format) => Some(format),
-            FileFormat::UserOverride(id) => Some(registry::get(id)),
-            FileFormat::FromSize(_) | FileFormat::Unknown => None,
+            FileFormat::Ready(format, _)
+            | FileFormat::FromHeader(format, _)
+            | FileFormat::MetaOverride(format, _) => Some(format),
+            FileFormat::UserOverride(id, _) => Some(registry::get(id)),
+            FileFormat::FromSize(..) | FileFormat::Unknown => None,
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn provenance(&self) -> Option<&Provenance> {
+        match self {
+            FileFormat::Ready(_, provenance)
+            | FileFormat::FromHeader(_, provenance)
+            | FileFormat::MetaOverride(_, provenance)
+            | FileFormat::UserOverride(_, provenance)
+            | FileFormat::FromSize(_, provenance) => Some(provenance),
+            FileFormat::Unknown => None,
         }
     }
 
@@ -445,11 +653,11 @@ impl FileFormat {
     #[cfg_attr(feature = "debug-formats", instrument(ret))]
     pub fn get_all(&self) -> Vec<&TextureFormat> {
         match self {
-            FileFormat::Ready(ref format)
-            | FileFormat::FromHeader(ref format)
-            | FileFormat::MetaOverride(ref format) => vec![format],
-            FileFormat::UserOverride(id) => vec![registry::get(id)],
-            FileFormat::FromSize(formats) => registry::get_all(formats),
+            FileFormat::Ready(ref format, _)
+            | FileFormat::FromHeader(ref format, _)
+            | FileFormat::MetaOverride(ref format, _) => vec![format],
+            FileFormat::UserOverride(id, _) => vec![registry::get(id)],
+            FileFormat::FromSize(formats, _) => registry::get_all(formats),
             FileFormat::Unknown => vec![],
         }
     }
@@ -476,6 +684,7 @@ pub fn output_files(
             }
         }
         FileType::Texture => Ok(as_images(format, files)),
+        FileType::Lut => Ok(as_textures(format, files)),
     }
 }
 
@@ -537,25 +746,25 @@ pub fn merge_file_formats(current: FileFormat, next: FileFormat) -> FileFormat {
     #[allow(clippy::match_same_arms)]
     let ret = match (&current, &next) {
         (FileFormat::Unknown, _) => next,
-        (FileFormat::Ready(_), _) => current,
-        (_, FileFormat::Ready(_)) => next,
-        (FileFormat::UserOverride(_), _) => current,
-        (_, FileFormat::UserOverride(_)) => next,
-        (FileFormat::MetaOverride(_), _) => current,
-        (_, FileFormat::MetaOverride(_)) => next,
-        (FileFormat::FromHeader(current_id), FileFormat::FromHeader(next_id))
+        (FileFormat::Ready(..), _) => current,
+        (_, FileFormat::Ready(..)) => next,
+        (FileFormat::UserOverride(..), _) => current,
+        (_, FileFormat::UserOverride(..)) => next,
+        (FileFormat::MetaOverride(..), _) => current,
+        (_, FileFormat::MetaOverride(..)) => next,
+        (FileFormat::FromHeader(current_id, _), FileFormat::FromHeader(next_id, _))
             if current_id != next_id =>
         {
             FileFormat::Unknown
         }
-        (FileFormat::FromHeader(_), _) => current,
-        (_, FileFormat::FromHeader(_)) => next,
-        (FileFormat::FromSize(current_ids), FileFormat::FromSize(next_ids))
+        (FileFormat::FromHeader(..), _) => current,
+        (_, FileFormat::FromHeader(..)) => next,
+        (FileFormat::FromSize(current_ids, _), FileFormat::FromSize(next_ids, _))
             if current_ids != next_ids =>
         {
             FileFormat::Unknown
         }
-        (FileFormat::FromSize(_), _) => current,
+        (FileFormat::FromSize(..), _) => current,
     };
 
     #[cfg(feature = "debug-formats")]
@@ -574,50 +783,95 @@ pub fn merge_file_format_iter(iter: impl Iterator<Item = FileFormat>) -> FileFor
 pub fn format_for_texture_file(file: &Utf8Path) -> FileFormat {
     let registry = registry();
 
-    /*if let Some(format) = registry.get_override(file) {
-        FileFormat::MetaOverride(format)
-    } else*/ if !file.exists() {
+    if !file.exists() {
         FileFormat::Unknown
-    } else if let Ok((Some(format), _)) = texture_file::read_header(file).log_failure() {
-        FileFormat::FromHeader(format.into())
+    } else if let Ok((Some(header), _)) = texture_file::read_header(file).log_failure() {
+        FileFormat::FromHeader(header.into(), Provenance::from_header(file, &header))
+    } else if let Some((pattern, id)) = registry.override_for(file) {
+        FileFormat::Ready(*registry.get(id), Provenance::from_pattern(file, pattern))
     } else if let Ok(len) = std::fs::metadata(file).map(|m| m.len() as usize) {
-        FileFormat::FromSize(registry.formats_with_size(len))
+        let ids = registry.formats_with_size(len);
+        let provenance = Provenance::from_size(file, len, ids.len());
+        FileFormat::FromSize(ids, provenance)
     } else {
         FileFormat::Unknown
     }
 }
 
 #[cfg_attr(feature = "debug-inputs", instrument(ret))]
-pub fn format_for_image_file(file: &Utf8Path) -> FileFormat {
-    let file = Utf8PathBuf::from(base_name(file));
+pub fn format_for_image_file(image_file: &Utf8Path) -> FileFormat {
+    let file = Utf8PathBuf::from(base_name(image_file));
 
-    try_read_meta(&file)
-        .map(FileFormat::MetaOverride)
+    try_read_embedded(image_file)
+        .or_else(|| try_read_meta(&file))
+        .map(|(format, provenance)| FileFormat::MetaOverride(format, provenance))
         .unwrap_or_else(|| format_for_texture_file(&file.with_extension("texture")))
 }
 
 #[cfg_attr(feature = "debug-inputs", instrument(ret))]
 fn try_metafiles(file: &Utf8Path) -> Option<Utf8PathBuf> {
-    let meta = file.with_extension("json");
+    let meta = sidecar_file(file);
     if meta.exists() {
         return Some(meta);
     }
 
-    let meta = file.with_file_name(base_name(file)).with_extension("json");
+    // Older exports and hand-written overrides
+    let meta = file.with_extension(META_EXTENSION);
+    if meta.exists() {
+        return Some(meta);
+    }
+
+    let meta = file.with_file_name(base_name(file)).with_extension(META_EXTENSION);
     (meta.exists()).then_some(meta)
 }
 
-pub fn try_read_meta(file: &Utf8Path) -> Option<TextureFormat> {
+/// The format stored inside an exported image, see [`crate::embed`].
+pub fn try_read_embedded(image_file: &Utf8Path) -> Option<(TextureFormat, Provenance)> {
+    let embedded = embed::read(image_file)?;
+    let format = embedded
+        .format()
+        .log_failure_with(|| format!("Unknown format embedded in {image_file}"))?;
+    let provenance = Provenance::from_embedded(image_file, embedded.header);
+
+    Some((
+        TextureFormat {
+            source: provenance.source,
+            ..format
+        },
+        provenance,
+    ))
+}
+
+pub fn try_read_meta(file: &Utf8Path) -> Option<(TextureFormat, Provenance)> {
     let metafile = try_metafiles(file)?;
 
-    let meta = std::fs::read(file)
-        .log_failure_with(|| format!("Failed to read meta file {metafile}"))
-        .ok()?;
-    let format: TextureFormat = serde_json::from_slice(&meta)
+    let sidecar = Sidecar::load(&metafile)
         .log_failure_with(|| format!("Failed to read meta file {metafile}"))
         .ok()?;
 
-    Some(format)
+    let mut provenance = Provenance::from_meta(&metafile);
+    provenance.header = sidecar.header.clone();
+    if sidecar.version > 1 {
+        let changed = sidecar.changed_sources(&metafile);
+        provenance.detail = Some(if changed.is_empty() {
+            format!("Exported by SpiderTex {}", sidecar.spidertex)
+        } else {
+            let names: Vec<&str> = changed.iter().map(|source| source.name.as_str()).collect();
+            format!(
+                "Exported by SpiderTex {}, the original {} changed since",
+                sidecar.spidertex,
+                names.join(", ")
+            )
+        });
+    }
+
+    Some((
+        TextureFormat {
+            source: provenance.source,
+            ..sidecar.format
+        },
+        provenance,
+    ))
 }
 
 pub fn format_for_file<'r>(file: &Utf8Path) -> FileFormat {
@@ -72,8 +72,25 @@ pub trait DxgiFormatExt {
     fn is_compressed(self) -> bool;
     #[must_use]
     fn is_srgb(self) -> bool;
+    /// The same layout without the sRGB curve
+    #[must_use]
+    fn linear_format(self) -> Self;
+    /// The same layout with the sRGB curve, if there is one
+    #[must_use]
+    fn srgb_format(self) -> Self;
 }
 
+/// Formats that only differ in whether they use the sRGB curve
+const SRGB_PAIRS: &[(DXGI_FORMAT, DXGI_FORMAT)] = &[
+    (DXGI_FORMAT_R8G8B8A8_UNORM, DXGI_FORMAT_R8G8B8A8_UNORM_SRGB),
+    (DXGI_FORMAT_B8G8R8A8_UNORM, DXGI_FORMAT_B8G8R8A8_UNORM_SRGB),
+    (DXGI_FORMAT_B8G8R8X8_UNORM, DXGI_FORMAT_B8G8R8X8_UNORM_SRGB),
+    (DXGI_FORMAT_BC1_UNORM, DXGI_FORMAT_BC1_UNORM_SRGB),
+    (DXGI_FORMAT_BC2_UNORM, DXGI_FORMAT_BC2_UNORM_SRGB),
+    (DXGI_FORMAT_BC3_UNORM, DXGI_FORMAT_BC3_UNORM_SRGB),
+    (DXGI_FORMAT_BC7_UNORM, DXGI_FORMAT_BC7_UNORM_SRGB),
+];
+
 impl DxgiFormatExt for DXGI_FORMAT {
     #[inline]
     fn display(self) -> DxgiFormatDisplay { DxgiFormatDisplay(self) }
@@ -163,31 +180,73 @@ impl DxgiFormatExt for DXGI_FORMAT {
     #[inline]
     #[must_use]
     fn is_srgb(self) -> bool { dxtex::is_srgb(self) }
+
+    #[must_use]
+    fn linear_format(self) -> Self {
+        SRGB_PAIRS
+            .iter()
+            .find(|(_, srgb)| *srgb == self)
+            .map_or(self, |(linear, _)| *linear)
+    }
+
+    #[must_use]
+    fn srgb_format(self) -> Self {
+        SRGB_PAIRS
+            .iter()
+            .find(|(linear, _)| *linear == self)
+            .map_or(self, |(_, srgb)| *srgb)
+    }
+}
+
+pub const FORMAT_NAMES: &[(DXGI_FORMAT, &str)] = &[
+    (DXGI_FORMAT_BC1_UNORM, "BC1"),
+    (DXGI_FORMAT_BC1_UNORM_SRGB, "BC1 sRGB"),
+    (DXGI_FORMAT_BC2_UNORM, "BC2"),
+    (DXGI_FORMAT_BC2_UNORM_SRGB, "BC2 sRGB"),
+    (DXGI_FORMAT_BC3_UNORM, "BC3"),
+    (DXGI_FORMAT_BC3_UNORM_SRGB, "BC3 sRGB"),
+    (DXGI_FORMAT_BC4_UNORM, "BC4"),
+    (DXGI_FORMAT_BC5_UNORM, "BC5"),
+    (DXGI_FORMAT_BC6H_UF16, "BC6"),
+    (DXGI_FORMAT_BC7_UNORM, "BC7"),
+    (DXGI_FORMAT_BC7_UNORM_SRGB, "BC7 sRGB"),
+    (DXGI_FORMAT_R8G8B8A8_UNORM, "RGBA8"),
+    (DXGI_FORMAT_R8G8B8A8_UNORM_SRGB, "RGBA8 sRGB"),
+    (DXGI_FORMAT_R8_UNORM, "Luma"),
+    (DXGI_FORMAT_R32G32B32A32_FLOAT, "HDR 32f"),
+    (DXGI_FORMAT_R16G16B16A16_FLOAT, "HDR 16f"),
+];
+
+/// Parses either a numeric DXGI format or one of the names in
+/// [`FORMAT_NAMES`], ignoring case and spaces.
+#[must_use]
+pub fn parse_dxgi_format(name: &str) -> Option<DXGI_FORMAT> {
+    if let Ok(number) = name.trim().parse::<u32>() {
+        return Some(DXGI_FORMAT(number));
+    }
+
+    let normalize = |name: &str| -> String {
+        name.chars()
+            .filter(|c| !c.is_whitespace() && *c != '_')
+            .collect::<String>()
+            .to_ascii_lowercase()
+    };
+    let name = normalize(name);
+
+    FORMAT_NAMES
+        .iter()
+        .find(|(_, known)| normalize(known) == name)
+        .map(|(format, _)| *format)
 }
 
 pub struct DxgiFormatDisplay(DXGI_FORMAT);
 
 impl Display for DxgiFormatDisplay {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(match self.0 {
-            DXGI_FORMAT_BC1_UNORM => "BC1",
-            DXGI_FORMAT_BC1_UNORM_SRGB => "BC1 sRGB",
-            DXGI_FORMAT_BC2_UNORM => "BC2",
-            DXGI_FORMAT_BC2_UNORM_SRGB => "BC2 sRGB",
-            DXGI_FORMAT_BC3_UNORM => "BC3",
-            DXGI_FORMAT_BC3_UNORM_SRGB => "BC3 sRGB",
-            DXGI_FORMAT_BC4_UNORM => "BC4",
-            DXGI_FORMAT_BC5_UNORM => "BC5",
-            DXGI_FORMAT_BC6H_UF16 => "BC6",
-            DXGI_FORMAT_BC7_UNORM => "BC7",
-            DXGI_FORMAT_BC7_UNORM_SRGB => "BC7 sRGB",
-            DXGI_FORMAT_R8G8B8A8_UNORM => "RGBA8",
-            DXGI_FORMAT_R8G8B8A8_UNORM_SRGB => "RGBA8 sRGB",
-            DXGI_FORMAT_R8_UNORM => "Luma",
-            DXGI_FORMAT_R32G32B32A32_FLOAT => "HDR 32f",
-            DXGI_FORMAT_R16G16B16A16_FLOAT => "HDR 16f",
-            _ => return write!(f, "{:?}", self.0),
-        })
+        match FORMAT_NAMES.iter().find(|(format, _)| *format == self.0) {
+            Some((_, name)) => f.write_str(name),
+            None => write!(f, "{:?}", self.0),
+        }
     }
 }
 
@@ -6,11 +6,15 @@ use crate::prelude::*;
 use crate::registry::FormatId;
 use crate::texture_file::{self, TEXTURE_HEADER_SIZE};
 pub(crate) mod dxgi;
-pub use dxgi::DxgiFormatExt;
+pub use dxgi::{parse_dxgi_format, DxgiFormatExt};
+mod provenance;
+pub use provenance::{Confidence, Provenance, Source};
 mod texture;
-pub use texture::{Source, TextureFormat};
+pub use texture::TextureFormat;
 mod misc;
 pub use misc::{ColorPlanes, Dimensions, ImageFormatExt};
+mod sniff;
+pub use sniff::{sniff, SniffReport, SniffScore};
 
 pub fn print_formats<'a>(iter: impl Iterator<Item = &'a FormatId>) {
     for id in iter {
@@ -18,62 +22,58 @@ pub fn print_formats<'a>(iter: impl Iterator<Item = &'a FormatId>) {
     }
 }
 
-#[must_use]
-pub fn guess_dimensions(len: usize, formats: &[TextureFormat]) -> Option<(Dimensions, bool)> {
-    let len_without_header = len - TEXTURE_HEADER_SIZE;
-
-    // FIXME: improve this to scan all formats
-
-    if let Some(format) = formats.first().log_failure() {
-        if format.standard.data_size == len {
-            Some((format.standard, false))
-        } else if format.standard.data_size == len_without_header {
-            Some((format.standard, true))
-        } else if let Some(highres) = format.highres {
-            if highres.data_size == len {
-                Some((highres, false))
-            } else if highres.data_size == len_without_header {
-                Some((highres, true))
-            } else {
-                None
-            }
-        } else {
-            None
-        }
-    } else {
-        None
-    }
+/// A texture inside a buffer, found by [`guess_dimensions`].
+#[derive(Debug, Copy, Clone)]
+pub struct DimensionMatch<'f> {
+    pub format:       &'f TextureFormat,
+    pub dimensions:   Dimensions,
+    pub highres:      bool,
+    /// The buffer starts with a texture header that has to be skipped
+    pub strip_header: bool,
 }
 
+/// Finds every candidate texture a buffer of `len` bytes could hold, with or
+/// without a texture header. Exact size matches are ranked before matches
+/// that need the header stripped. Within each group matches keep candidate
+/// order and, for each candidate, the standard texture comes before the
+/// high-resolution one.
 #[must_use]
-pub fn guess_dimensions_2(len: usize, formats: &[&TextureFormat]) -> Option<(Dimensions, bool)> {
-    let len_without_header = if len <= TEXTURE_HEADER_SIZE {
-        len
-    } else {
-        len - TEXTURE_HEADER_SIZE
-    };
-
-    // FIXME: improve this to scan all formats
-
-    if let Some(format) = formats.first().log_failure() {
-        if format.standard.data_size == len {
-            Some((format.standard, false))
-        } else if format.standard.data_size == len_without_header {
-            Some((format.standard, true))
-        } else if let Some(highres) = format.highres {
-            if highres.data_size == len {
-                Some((highres, false))
-            } else if highres.data_size == len_without_header {
-                Some((highres, true))
+pub fn guess_dimensions<'f>(
+    len: usize,
+    formats: impl IntoIterator<Item = &'f TextureFormat>,
+) -> Vec<DimensionMatch<'f>> {
+    let len_without_header = len.checked_sub(TEXTURE_HEADER_SIZE);
+    let mut matches = Vec::new();
+
+    for format in formats {
+        let textures = [(format.standard, false)]
+            .into_iter()
+            .chain(format.highres.map(|highres| (highres, true)));
+
+        for (dimensions, highres) in textures {
+            let strip_header = if dimensions.data_size == len {
+                false
+            } else if Some(dimensions.data_size) == len_without_header {
+                true
             } else {
-                None
-            }
-        } else {
-            None
+                continue;
+            };
+
+            matches.push(DimensionMatch {
+                format,
+                dimensions,
+                highres,
+                strip_header,
+            });
         }
-    } else {
-        None
     }
+
+    if matches.is_empty() {
+        event!(DEBUG, len, "No candidate format matches the buffer size");
+    }
+    matches.sort_by_key(|found| found.strip_header);
+
+    matches
 }
 
 // TODO: this baby doesn't work anymore it needs to be adapted for groups
@@ -141,3 +141,51 @@ pub fn probe_textures_2<'a, 'r>(
         }
     }
 }
+
+#[test]
+fn test_guess_dimensions_ranking() {
+    use windows::Win32::Graphics::Dxgi::Common::DXGI_FORMAT_BC7_UNORM;
+
+    let dimensions = |data_size| Dimensions {
+        data_size,
+        width: 256,
+        height: 256,
+        mipmaps: 1,
+    };
+    let format = |standard, highres: Option<usize>| TextureFormat {
+        dxgi_format: DXGI_FORMAT_BC7_UNORM,
+        standard:    dimensions(standard),
+        highres:     highres.map(dimensions),
+        array_size:  1,
+        stex_format: (0, 0),
+        source:      Source::default(),
+    };
+
+    let len = 4096;
+    let candidates = [
+        format(2048, None),
+        format(len - TEXTURE_HEADER_SIZE, None),
+        format(len, Some(len * 4)),
+        format(len / 4, Some(len)),
+        format(len, Some(len - TEXTURE_HEADER_SIZE)),
+    ];
+    let ranked: Vec<(usize, bool, bool)> = guess_dimensions(len, &candidates)
+        .into_iter()
+        .map(|found| {
+            let index = candidates
+                .iter()
+                .position(|candidate| std::ptr::eq(candidate, found.format))
+                .unwrap();
+            (index, found.highres, found.strip_header)
+        })
+        .collect();
+
+    assert_eq!(ranked, [
+        (2, false, false),
+        (3, true, false),
+        (4, false, false),
+        (1, false, true),
+        (4, true, true),
+    ]);
+    assert!(guess_dimensions(len, &candidates[.. 1]).is_empty());
+}
@@ -0,0 +1,170 @@
+use std::fmt::Display;
+
+use camino::{Utf8Path, Utf8PathBuf};
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Source {
+    #[default]
+    FromHeader,
+    FromSize,
+    FromFilename,
+    UserOverride,
+    MetaOverride,
+    Embedded,
+    Sniffed,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Confidence {
+    /// One of several formats that could fit
+    #[default]
+    Guess,
+    /// Picked by a heuristic or a file name rule
+    Likely,
+    /// Read from a header, a metadata file or chosen by the user
+    Certain,
+}
+
+/// Where a detected format came from and how much it can be trusted.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Provenance {
+    pub source:     Source,
+    pub confidence: Confidence,
+    /// The file the format was read or guessed from
+    pub file:       Option<Utf8PathBuf>,
+    /// The raw format header, as stored in the registry
+    pub header:     Option<String>,
+    /// The registry length entry that matched
+    pub size:       Option<usize>,
+    /// The registry override pattern that matched the file name
+    pub pattern:    Option<String>,
+    /// Anything else worth telling the user, e.g. sniffing scores
+    pub detail:     Option<String>,
+}
+
+impl Provenance {
+    #[must_use]
+    pub fn from_header(file: &Utf8Path, header: &crate::texture_file::FormatHeader) -> Self {
+        Self {
+            source: Source::FromHeader,
+            confidence: Confidence::Certain,
+            file: Some(file.to_owned()),
+            header: Some(header.as_hexstring()),
+            ..Default::default()
+        }
+    }
+
+    #[must_use]
+    pub fn from_size(file: &Utf8Path, size: usize, candidates: usize) -> Self {
+        Self {
+            source: Source::FromSize,
+            confidence: if candidates == 1 {
+                Confidence::Likely
+            } else {
+                Confidence::Guess
+            },
+            file: Some(file.to_owned()),
+            size: Some(size),
+            ..Default::default()
+        }
+    }
+
+    #[must_use]
+    pub fn from_meta(metafile: &Utf8Path) -> Self {
+        Self {
+            source: Source::MetaOverride,
+            confidence: Confidence::Certain,
+            file: Some(metafile.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    #[must_use]
+    pub fn from_embedded(image_file: &Utf8Path, header: Option<String>) -> Self {
+        Self {
+            source: Source::Embedded,
+            confidence: Confidence::Certain,
+            file: Some(image_file.to_owned()),
+            header,
+            ..Default::default()
+        }
+    }
+
+    #[must_use]
+    pub fn from_pattern(file: &Utf8Path, pattern: &str) -> Self {
+        Self {
+            source: Source::FromFilename,
+            confidence: Confidence::Likely,
+            file: Some(file.to_owned()),
+            pattern: Some(pattern.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    #[must_use]
+    pub fn user_override() -> Self {
+        Self {
+            source: Source::UserOverride,
+            confidence: Confidence::Certain,
+            ..Default::default()
+        }
+    }
+
+    /// Marks a size match as picked by content sniffing.
+    #[must_use]
+    pub fn sniffed(self, detail: impl Into<String>) -> Self {
+        Self {
+            source: Source::Sniffed,
+            confidence: Confidence::Likely,
+            detail: Some(detail.into()),
+            ..self
+        }
+    }
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::FromHeader => "texture header",
+            Self::FromSize => "file size",
+            Self::FromFilename => "file name",
+            Self::UserOverride => "user override",
+            Self::MetaOverride => "metadata file",
+            Self::Embedded => "image metadata",
+            Self::Sniffed => "file size and contents",
+        })
+    }
+}
+
+impl Display for Confidence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Guess => "guess",
+            Self::Likely => "likely",
+            Self::Certain => "certain",
+        })
+    }
+}
+
+impl Display for Provenance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "From {} ({})", self.source, self.confidence)?;
+
+        if let Some(file) = &self.file {
+            write!(f, "\n  File: {file}")?;
+        }
+        if let Some(header) = &self.header {
+            write!(f, "\n  Header: {header}")?;
+        }
+        if let Some(size) = self.size {
+            write!(f, "\n  Size: {size} bytes")?;
+        }
+        if let Some(pattern) = &self.pattern {
+            write!(f, "\n  Pattern: {pattern}")?;
+        }
+        if let Some(detail) = &self.detail {
+            write!(f, "\n  {detail}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,246 @@
+//! Picks between formats that share a byte length by decoding the data under
+//! each of them and scoring how much the result looks like an image.
+//!
+//! Each candidate gets a cost, lower is more plausible:
+//!
+//! * Blockiness: pixel differences across 4×4 block boundaries divided by the
+//!   differences inside blocks. A correct layout is close to 1, decoding with
+//!   the wrong width or format shows up as hard seams on the block grid.
+//! * Entropy of the luma histogram in bits. Noise from a wrong decode spreads
+//!   over the whole range and gets close to 8.
+//! * For normal map formats, the share of decoded vectors that don't fit on a
+//!   unit hemisphere.
+
+use std::cmp::Ordering;
+
+use windows::Win32::Graphics::Dxgi::Common::{
+    DXGI_FORMAT_BC5_SNORM,
+    DXGI_FORMAT_BC5_UNORM,
+    DXGI_FORMAT_R8G8B8A8_UNORM,
+};
+
+use super::guess_dimensions;
+use crate::dxtex::{DXImage, TEX_FILTER_FLAGS};
+use crate::prelude::*;
+
+const BLOCK: usize = 4;
+/// The best candidate only wins if the next one costs this much more
+const WINNING_MARGIN: f32 = 1.25;
+
+#[derive(Debug, Clone)]
+pub struct SniffScore {
+    pub format:       TextureFormat,
+    /// Why the candidate couldn't be decoded, if it couldn't
+    pub error:        Option<String>,
+    pub blockiness:   f32,
+    pub entropy:      f32,
+    pub normal_error: Option<f32>,
+    pub cost:         f32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SniffReport {
+    /// Every candidate, most plausible first
+    pub scores: Vec<SniffScore>,
+    pub winner: Option<TextureFormat>,
+}
+
+impl SniffScore {
+    #[inline]
+    #[must_use]
+    pub const fn decoded(&self) -> bool { self.error.is_none() }
+}
+
+impl std::fmt::Display for SniffScore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(error) = &self.error {
+            return write!(f, "{}: not decodable ({error})", self.format);
+        }
+
+        write!(
+            f,
+            "{}: cost {:.2} (blockiness {:.2}, entropy {:.2}",
+            self.format, self.cost, self.blockiness, self.entropy
+        )?;
+        if let Some(normal_error) = self.normal_error {
+            write!(f, ", normal error {normal_error:.2}")?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Scores every candidate for `data`, which may start with a texture header.
+#[must_use]
+pub fn sniff(data: &[u8], candidates: &[TextureFormat]) -> SniffReport {
+    let mut scores: Vec<SniffScore> = candidates
+        .iter()
+        .map(|format| score(data, format))
+        .collect();
+
+    scores.sort_by(|a, b| {
+        b.decoded()
+            .cmp(&a.decoded())
+            .then(a.cost.partial_cmp(&b.cost).unwrap_or(Ordering::Equal))
+    });
+
+    let winner = match scores.as_slice() {
+        [best, ..] if !best.decoded() => None,
+        [best] => Some(best.format),
+        [best, next, ..] if !next.decoded() || next.cost > best.cost * WINNING_MARGIN => {
+            Some(best.format)
+        }
+        _ => None,
+    };
+
+    SniffReport { scores, winner }
+}
+
+fn score(data: &[u8], format: &TextureFormat) -> SniffScore {
+    match decode_rgba(data, format) {
+        Ok((width, height, pixels)) => {
+            let blockiness = blockiness(width, height, &pixels);
+            let entropy = entropy(&pixels);
+            let normal_error = is_normal_format(format.dxgi_format).then(|| normal_error(&pixels));
+
+            SniffScore {
+                format: *format,
+                error: None,
+                blockiness,
+                entropy,
+                normal_error,
+                cost: (blockiness - 1.0).max(0.0) + entropy / 8.0 + normal_error.unwrap_or(0.0),
+            }
+        }
+        Err(error) => SniffScore {
+            format:       *format,
+            error:        Some(error.to_string()),
+            blockiness:   f32::INFINITY,
+            entropy:      f32::INFINITY,
+            normal_error: None,
+            cost:         f32::INFINITY,
+        },
+    }
+}
+
+/// Decodes the first image of the top mip level to RGBA8.
+fn decode_rgba(data: &[u8], format: &TextureFormat) -> Result<(usize, usize, Vec<u8>)> {
+    let found = guess_dimensions(data.len(), [format])
+        .into_iter()
+        .next()
+        .ok_or_else(|| Error::message("Size doesn't match"))?;
+    let data = if found.strip_header {
+        &data[TEXTURE_HEADER_SIZE ..]
+    } else {
+        data
+    };
+
+    let dimensions = found.dimensions;
+    let image = DXImage::with_dimensions(format.dxgi_format, dimensions, format.array_size, data)?
+        .map_if(format.dxgi_format.is_compressed(), DXImage::decompress)?
+        .convert(DXGI_FORMAT_R8G8B8A8_UNORM, TEX_FILTER_FLAGS(0))?;
+
+    let mut pixels = image.pixels()?;
+    pixels.truncate(dimensions.width * dimensions.height * 4);
+
+    Ok((dimensions.width, dimensions.height, pixels))
+}
+
+const fn is_normal_format(format: DXGI_FORMAT) -> bool {
+    format.0 == DXGI_FORMAT_BC5_UNORM.0 || format.0 == DXGI_FORMAT_BC5_SNORM.0
+}
+
+fn luma(pixel: &[u8]) -> f32 {
+    0.299 * f32::from(pixel[0]) + 0.587 * f32::from(pixel[1]) + 0.114 * f32::from(pixel[2])
+}
+
+fn blockiness(width: usize, height: usize, pixels: &[u8]) -> f32 {
+    let at = |x: usize, y: usize| luma(&pixels[(y * width + x) * 4 ..]);
+
+    let (mut edge, mut edge_count) = (0.0, 0_usize);
+    let (mut inner, mut inner_count) = (0.0, 0_usize);
+    let mut add = |diff: f32, on_edge: bool| {
+        if on_edge {
+            edge += diff;
+            edge_count += 1;
+        } else {
+            inner += diff;
+            inner_count += 1;
+        }
+    };
+
+    for y in 0 .. height {
+        for x in 0 .. width {
+            if x + 1 < width {
+                add((at(x, y) - at(x + 1, y)).abs(), (x + 1) % BLOCK == 0);
+            }
+            if y + 1 < height {
+                add((at(x, y) - at(x, y + 1)).abs(), (y + 1) % BLOCK == 0);
+            }
+        }
+    }
+
+    if edge_count == 0 || inner_count == 0 {
+        return 1.0;
+    }
+
+    let edge = edge / edge_count as f32;
+    let inner = inner / inner_count as f32;
+
+    // The offsets keep flat images at 1 instead of dividing by zero
+    (edge + 1.0) / (inner + 1.0)
+}
+
+fn entropy(pixels: &[u8]) -> f32 {
+    let mut histogram = [0_usize; 256];
+    for pixel in pixels.chunks_exact(4) {
+        histogram[luma(pixel) as usize] += 1;
+    }
+
+    let total = (pixels.len() / 4) as f32;
+    histogram
+        .iter()
+        .filter(|count| **count > 0)
+        .map(|count| {
+            let p = *count as f32 / total;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// The fraction of pixels whose X and Y components don't fit on a unit
+/// hemisphere.
+fn normal_error(pixels: &[u8]) -> f32 {
+    let to_unit = |value: u8| f32::from(value) / 127.5 - 1.0;
+
+    let invalid = pixels
+        .chunks_exact(4)
+        .filter(|pixel| {
+            let (x, y) = (to_unit(pixel[0]), to_unit(pixel[1]));
+            x * x + y * y > 1.05
+        })
+        .count();
+
+    invalid as f32 / (pixels.len() / 4).max(1) as f32
+}
+
+#[test]
+fn test_sniff_metrics() {
+    let (width, height) = (16, 16);
+    let gradient: Vec<u8> = (0 .. width * height)
+        .flat_map(|i| {
+            let value = ((i % width) * 16) as u8;
+            [value, value, value, 255]
+        })
+        .collect();
+    let noise: Vec<u8> = (0 .. width * height)
+        .flat_map(|i: usize| {
+            let value = (i.wrapping_mul(2_654_435_761) >> 7) as u8;
+            [value, value.rotate_left(3), value.rotate_left(5), 255]
+        })
+        .collect();
+
+    assert!(blockiness(width, height, &gradient) < 1.1);
+    assert!(entropy(&gradient) < entropy(&noise));
+    assert!(normal_error(&[128, 128, 255, 255]) < f32::EPSILON);
+    assert!(normal_error(&[255, 255, 0, 255]) > 0.5);
+}
@@ -5,24 +5,13 @@ use std::path::Path;
 use serde::{Deserialize, Serialize};
 use windows::Win32::Graphics::Dxgi::Common::DXGI_FORMAT;
 
-use super::{dxgi, ColorPlanes, Dimensions, ImageFormat};
+use super::{dxgi, ColorPlanes, Dimensions, ImageFormat, Source};
 use crate::prelude::*;
 
-#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
-pub enum Source {
-    #[default]
-    FromHeader,
-    FromSize,
-    FromFilename,
-    UserOverride,
-    MetaOverride,
-}
-
 #[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq)]
 pub struct TextureFormat {
     #[serde(with = "dxgi::serde")]
     pub dxgi_format: DXGI_FORMAT,
-    // pub stex_format:           (u8, u8),
     pub standard:    Dimensions,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub highres:     Option<Dimensions>,
@@ -31,6 +20,9 @@ pub struct TextureFormat {
         skip_serializing_if = "is_default_array_size"
     )]
     pub array_size:  usize,
+    /// The `stex_format` and `planes` bytes of the format header
+    #[serde(default, skip_serializing_if = "is_default_stex_format")]
+    pub stex_format: (u8, u8),
     #[serde(default, skip)]
     pub source:      Source,
 }
@@ -47,6 +39,25 @@ impl TextureFormat {
     #[must_use]
     pub fn id(&self) -> FormatId { FormatId::from(self) }
 
+    /// Compares every field except the source, unlike `==` which only
+    /// compares ids.
+    #[must_use]
+    pub fn same_layout(&self, other: &Self) -> bool {
+        let same_dimensions = |a: Dimensions, b: Dimensions| {
+            a == b && a.data_size == b.data_size && a.mipmaps == b.mipmaps
+        };
+
+        self.dxgi_format == other.dxgi_format
+            && same_dimensions(self.standard, other.standard)
+            && match (self.highres, other.highres) {
+                (Some(a), Some(b)) => same_dimensions(a, b),
+                (None, None) => true,
+                _ => false,
+            }
+            && self.array_size == other.array_size
+            && self.stex_format == other.stex_format
+    }
+
     #[inline]
     #[must_use]
     pub const fn is_1d(&self) -> bool { self.standard.height == 1 }
@@ -65,7 +76,8 @@ impl TextureFormat {
         }
     }
 
-    // FIXME: look into this
+    /// The image format exports use unless
+    /// [`crate::options::ExportOptions::image_format_for`] picks another one.
     #[inline]
     #[must_use]
     pub fn default_image_format(&self) -> ImageFormat {
@@ -118,9 +130,10 @@ impl TextureFormat {
     #[inline]
     pub fn to_header(&self) -> Result<texture_file::FormatHeader> {
         // TODO: create a fake header if necessary
-        let header_str = registry::raw_header(self.id())
+        let header_str = registry()
+            .raw_header_for(self)
             .ok_or_else(|| Error::message("Raw header not found"))?;
-        texture_file::FormatHeader::from_hexstring(&header_str)
+        texture_file::FormatHeader::from_hexstring(header_str)
     }
 
     #[inline]
@@ -247,6 +260,38 @@ impl TextureFormat {
         }
     }
 
+    /// The same texture with its largest version at `width` by `height`. An
+    /// HD texture keeps an SD one at half the size, mip counts follow
+    /// [`Dimensions::mip_levels`] and data sizes the DXGI format.
+    #[must_use]
+    pub fn with_resolution(&self, width: usize, height: usize) -> Self {
+        let dimensions = |width: usize, height: usize, is_highres: bool| {
+            let mut dimensions = Dimensions {
+                data_size: 0,
+                width,
+                height,
+                mipmaps: 1,
+            };
+            dimensions.mipmaps = dimensions.mip_levels(is_highres);
+            dimensions.data_size =
+                dxtex::expected_size_array(self.dxgi_format, dimensions, self.array_size);
+            dimensions
+        };
+
+        let (standard, highres) = if self.has_highres() {
+            let standard = dimensions((width / 2).max(1), (height / 2).max(1), false);
+            (standard, Some(dimensions(width, height, true)))
+        } else {
+            (dimensions(width, height, false), None)
+        };
+
+        Self {
+            standard,
+            highres,
+            ..*self
+        }
+    }
+
     #[inline]
     #[must_use]
     pub fn planes(&self) -> ColorPlanes { self.dxgi_format.planes() }
@@ -263,7 +308,7 @@ impl From<texture_file::FormatHeader> for TextureFormat {
 impl From<&texture_file::FormatHeader> for TextureFormat {
     fn from(header: &texture_file::FormatHeader) -> Self {
         let dxgi_format = DXGI_FORMAT(header.format.into());
-        // let stex_format = (header.stex_format, header.planes);
+        let stex_format = (header.stex_format, header.planes);
 
         let standard = Dimensions {
             data_size: header.sd_len as usize,
@@ -286,6 +331,7 @@ impl From<&texture_file::FormatHeader> for TextureFormat {
             standard,
             highres,
             array_size: header.array_size as usize,
+            stex_format,
         }
     }
 }
@@ -315,3 +361,10 @@ pub const fn default_array_size() -> usize { 1 }
 pub const fn is_default_array_size(array_size: &usize) -> bool {
     *array_size == default_array_size()
 }
+
+#[inline]
+#[must_use]
+#[allow(clippy::trivially_copy_pass_by_ref)]
+pub const fn is_default_stex_format(stex_format: &(u8, u8)) -> bool {
+    stex_format.0 == 0 && stex_format.1 == 0
+}
@@ -58,7 +58,7 @@ impl ImageInfo {
             image = image.resize_exact(
                 format.preferred_width() as u32,
                 format.preferred_height() as u32,
-                spidertexlib::IMAGERS_RESIZE_FILTER,
+                spidertexlib::options::ResizeFilter::default().imagers(),
             );
         }
 
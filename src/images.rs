@@ -115,7 +115,20 @@ impl Image for DxImport {
         // }
 
         if !format.aspect_ratio_matches(dimensions) {
-            warnings.push("Image has the wrong aspect ratio and will be distorted if imported.");
+            warnings.push(
+                "Image has the wrong aspect ratio and will be stretched, padded or cropped \
+                 depending on the import options.",
+            );
+        }
+
+        // Only pre-compressed files, the others get their mip levels generated
+        if dxgi_format == format.dxgi_format && dxgi_format.is_compressed() {
+            if let Some(mismatch) = crate::mips::layout_mismatch(format, &metadata) {
+                warnings.push(format!(
+                    "Image is already in {} but can't be used as it is: {mismatch}.",
+                    format.dxgi_format.display()
+                ));
+            }
         }
 
         let (correct_size, _is_lowres) = format.is_correct_size(dimensions);
@@ -150,7 +163,10 @@ impl Image for ImageRs {
         let dimensions = Self::read_dimensions(file)?;
 
         if !format.aspect_ratio_matches(dimensions) {
-            warnings.push("Image has the wrong aspect ratio and will be distorted if imported.");
+            warnings.push(
+                "Image has the wrong aspect ratio and will be stretched, padded or cropped \
+                 depending on the import options.",
+            );
         }
         let (needs_resize, _is_lowres) = format.is_correct_size(dimensions);
         if needs_resize {
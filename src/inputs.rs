@@ -16,6 +16,9 @@ use crate::files::{
     InputGroup,
     Uncategorized,
 };
+use crate::lut::is_cube_file;
+use crate::options::Options;
+use crate::tonemap::is_preview_file;
 use crate::prelude::*;
 use crate::util::{open_files_dialog, WalkArgs};
 
@@ -33,7 +36,7 @@ fn action(file: &Utf8Path) -> Action {
         .and_then(|ext| {
             if is_texture_ext(ext) {
                 Some(Action::Export)
-            } else if is_image_ext(ext) {
+            } else if is_image_ext(ext) || is_cube_file(file) {
                 Some(Action::Import)
             } else if is_ignored_ext(ext) {
                 Some(Action::Ignore)
@@ -191,6 +194,8 @@ pub fn group(
 ) -> BTreeMap<(FileType, String), Vec<Utf8PathBuf>> {
     let mut grouped: BTreeMap<(FileType, String), Vec<Utf8PathBuf>> = BTreeMap::new();
 
+    // Tone mapped previews are written next to HDR exports and never imported
+    let iter = iter.filter(|file| !is_preview_file(file));
     for (file_type, file) in iter.filter_map(|file| {
         Some((
             FileType::try_from(file.as_ref())
@@ -215,10 +220,10 @@ pub fn categorize(grouped: BTreeMap<(FileType, String), Vec<Utf8PathBuf>>) -> In
     Inputs { textures, images }
 }
 
-pub fn gather_from_args() -> Inputs {
-    let args = std::env::args().skip(1).map(Utf8PathBuf::from);
+pub fn gather_from_args() -> Result<(Options, Inputs)> {
+    let (options, files) = Options::from_args()?;
 
-    gather_iter(args)
+    Ok((options, gather_iter(files.into_iter())))
 }
 
 pub fn gather(from: impl Into<Utf8PathBuf>) -> Inputs { gather_iter(std::iter::once(from.into())) }
@@ -11,12 +11,24 @@ pub mod formats;
 pub mod registry;
 pub mod util;
 use dxtex::{compress_texture, decompress_texture};
+pub mod atlas;
+pub mod channels;
 pub mod convert;
+pub mod cubemap;
+pub mod embed;
 pub mod files;
 pub mod images;
 pub mod inputs;
+pub mod lut;
+pub mod metrics;
+pub mod mips;
+pub mod naming;
+pub mod normals;
+pub mod options;
 pub mod rgb;
+pub mod sidecar;
 pub mod texture_file;
+pub mod tonemap;
 
 pub const APP_TITLE: &str = concat!("Spider-Man Texture Converter v", env!("CARGO_PKG_VERSION"));
 
@@ -25,9 +37,8 @@ pub const APP_TITLE: &str = concat!("Spider-Man Texture Converter v", env!("CARG
 pub use std;
 
 pub const SUPPORTED_TEXTURE_EXTENSIONS: &[&str] = &["texture", "raw"];
-pub const SUPPORTED_IMAGE_EXTENSIONS: &[&str] = &["png", "tga", "dds", "hdr", "exr"];
+pub const SUPPORTED_IMAGE_EXTENSIONS: &[&str] = &["png", "tga", "dds", "hdr", "exr", "webp"];
 pub const META_EXTENSION: &str = "json";
-pub const IMAGERS_RESIZE_FILTER: image::imageops::FilterType = image::imageops::Lanczos3;
 pub const DEFAULT_IMAGE_FORMAT: ImageFormat = ImageFormat::Png;
 
 pub mod prelude {
@@ -52,11 +63,10 @@ use prelude::*;
 use crate::dxtex::{expected_size, expected_size_array};
 
 fn read_metadata_from_json(input_file: &Utf8Path) -> Result<TextureFormat> {
-    let metadata_file = input_file.with_extension("metadata.json");
-    let metadata_str = std::fs::read_to_string(&metadata_file)?;
-    let metadata = serde_json::from_str(&metadata_str)?;
+    let metadata_file = sidecar::sidecar_file(input_file);
+    let metadata = sidecar::Sidecar::load(&metadata_file)?;
 
-    Ok(metadata)
+    Ok(metadata.format)
 }
 
 fn read_metadata_from_texture(input_file: &Utf8Path) -> Result<Option<TextureFormat>> {
@@ -225,9 +235,12 @@ pub fn convert_texture_to_png(texture_file: &Utf8Path) -> Result<()> {
         )));
     }
 
-    let metadata_file = texture_file.with_extension("metadata.json");
-    let metadata = serde_json::to_string_pretty(&texture_info)?;
-    std::fs::write(&metadata_file, &metadata)?;
+    let mut sources = vec![texture_file.to_owned()];
+    if texture_info.has_highres() {
+        sources.push(hd_file.clone());
+    }
+    let metadata = sidecar::Sidecar::new(texture_info, &sources, &options::Options::default())?;
+    metadata.save(&sidecar::sidecar_file(texture_file))?;
 
     let (width, height, mipmaps, texture_data) = if let Some(highres) = texture_info.highres {
         (
@@ -424,7 +437,7 @@ pub fn convert_images_to_texture(
             dimensions.width,
             dimensions.height
         );
-        let resized = resize_image(image, dimensions);
+        let resized = resize_image(image, dimensions, options::ResizeFilter::default());
 
         event!(TRACE, "Calling DirectXTex");
         let buf = compress_texture(
@@ -452,12 +465,16 @@ pub fn convert_images_to_texture(
     Ok(vec)
 }
 
-pub fn resize_image(image: &DynamicImage, dimensions: Dimensions) -> Cow<'_, DynamicImage> {
+pub fn resize_image(
+    image: &DynamicImage,
+    dimensions: Dimensions,
+    filter: options::ResizeFilter,
+) -> Cow<'_, DynamicImage> {
     if image.width() as usize != dimensions.width || image.height() as usize != dimensions.height {
         Cow::Owned(image.resize_exact(
             dimensions.width as u32,
             dimensions.height as u32,
-            image::imageops::Lanczos3,
+            filter.imagers(),
         ))
     } else {
         Cow::Borrowed(image)
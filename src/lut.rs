@@ -0,0 +1,307 @@
+//! Colour grading LUTs as Adobe/Resolve `.cube` files.
+//!
+//! The game stores 3D LUTs as 32 slices of 32x32 pixels, with red along x,
+//! green along y and blue across the slices, which is also the order of a
+//! `.cube` table. 1D LUTs are 16x1 curves. Tables of another size are
+//! resampled to the texture, trilinearly for 3D LUTs.
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use windows::Win32::Graphics::Dxgi::Common::DXGI_FORMAT_R32G32B32A32_FLOAT;
+
+use crate::dxtex::DXImage;
+use crate::files::base_name;
+use crate::prelude::*;
+
+pub const CUBE_EXTENSION: &str = "cube";
+const FLOAT_FORMAT: DXGI_FORMAT = DXGI_FORMAT_R32G32B32A32_FLOAT;
+const DEFAULT_DOMAIN: [[f32; 3]; 2] = [[0.0; 3], [1.0; 3]];
+
+type Rgb = [f32; 3];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CubeLut {
+    pub title:  Option<String>,
+    /// Entries along each axis
+    pub size:   usize,
+    pub is_3d:  bool,
+    /// The input values of the first and last entries
+    pub domain: [Rgb; 2],
+    /// Red changes fastest, then green, then blue
+    pub table:  Vec<Rgb>,
+}
+
+#[inline]
+#[must_use]
+pub fn is_cube_file(file: &Utf8Path) -> bool {
+    file.extension()
+        .map_or(false, |ext| ext.eq_ignore_ascii_case(CUBE_EXTENSION))
+}
+
+/// The `.cube` file written next to the images of an exported LUT.
+#[must_use]
+pub fn cube_file(output_file: &Utf8Path) -> Utf8PathBuf {
+    Utf8PathBuf::from(base_name(output_file)).with_extension(CUBE_EXTENSION)
+}
+
+impl CubeLut {
+    pub fn load(file: &Utf8Path) -> Result<Self> {
+        std::fs::read_to_string(file)?
+            .parse()
+            .map_err(|error| Error::message(format!("Failed to read {file}: {error}")))
+    }
+
+    pub fn save(&self, file: &Utf8Path) -> Result<()> {
+        std::fs::write(file, self.to_string())?;
+
+        event!(TRACE, "Saved LUT to {file}");
+        Ok(())
+    }
+
+    /// Reads the table of a decoded LUT texture.
+    pub fn from_texture(image: &DXImage, format: &TextureFormat) -> Result<Self> {
+        let metadata = image.metadata()?;
+        let is_3d = format.is_2d_lut();
+        if is_3d && (metadata.width != metadata.height || metadata.arraySize != metadata.width) {
+            return error_message(format!(
+                "A {}x{} texture with {} slices isn't a 3D LUT",
+                metadata.width, metadata.height, metadata.arraySize
+            ));
+        }
+
+        let float = image.to_format(FLOAT_FORMAT)?;
+        let slices = if is_3d { metadata.arraySize } else { 1 };
+        let mut table = Vec::with_capacity(metadata.width * metadata.height * slices);
+        for slice in 0 .. slices {
+            let data = float.image(slice * metadata.mipLevels)?;
+            let rows = if is_3d { metadata.height } else { 1 };
+            table.extend(
+                data.chunks_exact(16)
+                    .take(metadata.width * rows)
+                    .map(|pixel| {
+                        let channel = |index: usize| {
+                            let bytes = &pixel[index * 4 .. index * 4 + 4];
+                            f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+                        };
+                        [channel(0), channel(1), channel(2)]
+                    }),
+            );
+        }
+
+        Ok(Self {
+            title: None,
+            size: metadata.width,
+            is_3d,
+            domain: DEFAULT_DOMAIN,
+            table,
+        })
+    }
+
+    /// Builds a float image for a LUT texture in `format`, resampled to its
+    /// size. Alpha is 1.
+    pub fn to_texture(&self, format: &TextureFormat) -> Result<DXImage> {
+        if self.is_3d != format.is_2d_lut() || (!self.is_3d && !format.is_1d_lut()) {
+            return error_message(format!(
+                "A {} .cube file doesn't fit a {}x{} texture with {} slices",
+                if self.is_3d { "3D" } else { "1D" },
+                format.standard.width,
+                format.standard.height,
+                format.array_size
+            ));
+        }
+
+        let lut = self.resample(format.standard.width);
+        let buffer: Vec<f32> = lut
+            .table
+            .iter()
+            .flat_map(|&[r, g, b]| [r, g, b, 1.0])
+            .collect();
+
+        let (height, slices) = if self.is_3d { (lut.size, lut.size) } else { (1, 1) };
+        DXImage::new_2d(FLOAT_FORMAT, lut.size, height, slices, 1, bytemuck::cast_slice(&buffer))
+    }
+
+    /// The LUT with `size` entries along each axis over the `0..1` domain.
+    #[must_use]
+    pub fn resample(&self, size: usize) -> Self {
+        if size == self.size && self.domain == DEFAULT_DOMAIN {
+            return self.clone();
+        }
+
+        let step = |index: usize| index as f32 / (size.max(2) - 1) as f32;
+        let table = if self.is_3d {
+            (0 .. size * size * size)
+                .map(|index| {
+                    let (r, g, b) = (index % size, index / size % size, index / size / size);
+                    self.sample([step(r), step(g), step(b)])
+                })
+                .collect()
+        } else {
+            (0 .. size).map(|index| self.sample([step(index); 3])).collect()
+        };
+
+        Self {
+            title: self.title.clone(),
+            size,
+            is_3d: self.is_3d,
+            domain: DEFAULT_DOMAIN,
+            table,
+        }
+    }
+
+    /// Looks up `rgb`, interpolating between the nearest entries.
+    #[must_use]
+    pub fn sample(&self, rgb: Rgb) -> Rgb {
+        let last = (self.size - 1) as f32;
+        let mut position = [0.0; 3];
+        for (axis, value) in position.iter_mut().enumerate() {
+            let [min, max] = [self.domain[0][axis], self.domain[1][axis]];
+            let t = if max > min { (rgb[axis] - min) / (max - min) } else { 0.0 };
+            *value = t.max(0.0).min(1.0) * last;
+        }
+
+        if !self.is_3d {
+            let mut result = [0.0; 3];
+            for (channel, value) in result.iter_mut().enumerate() {
+                let (low, high, t) = self.neighbours(position[channel]);
+                *value = lerp(self.table[low][channel], self.table[high][channel], t);
+            }
+            return result;
+        }
+
+        let (r0, r1, tr) = self.neighbours(position[0]);
+        let (g0, g1, tg) = self.neighbours(position[1]);
+        let (b0, b1, tb) = self.neighbours(position[2]);
+        let entry = |r: usize, g: usize, b: usize| self.table[(b * self.size + g) * self.size + r];
+
+        let mut result = [0.0; 3];
+        for (channel, value) in result.iter_mut().enumerate() {
+            let along_r = |g, b| lerp(entry(r0, g, b)[channel], entry(r1, g, b)[channel], tr);
+            let along_g = |b| lerp(along_r(g0, b), along_r(g1, b), tg);
+            *value = lerp(along_g(b0), along_g(b1), tb);
+        }
+        result
+    }
+
+    fn neighbours(&self, position: f32) -> (usize, usize, f32) {
+        let low = (position.floor() as usize).min(self.size - 1);
+        let high = (low + 1).min(self.size - 1);
+        (low, high, position - low as f32)
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 { a + (b - a) * t }
+
+fn parse_floats<const N: usize>(values: &[&str], line: &str) -> Result<[f32; N]> {
+    let mut result = [0.0; N];
+    if values.len() != N {
+        return error_message(format!("Expected {N} numbers in \"{line}\""));
+    }
+    for (value, text) in result.iter_mut().zip(values) {
+        *value = text
+            .parse()
+            .map_err(|_| Error::message(format!("{text} isn't a number in \"{line}\"")))?;
+    }
+    Ok(result)
+}
+
+impl Display for CubeLut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(title) = &self.title {
+            writeln!(f, "TITLE \"{title}\"")?;
+        }
+        writeln!(f, "LUT_{}D_SIZE {}", if self.is_3d { 3 } else { 1 }, self.size)?;
+        if self.domain != DEFAULT_DOMAIN {
+            let [[r0, g0, b0], [r1, g1, b1]] = self.domain;
+            writeln!(f, "DOMAIN_MIN {r0} {g0} {b0}")?;
+            writeln!(f, "DOMAIN_MAX {r1} {g1} {b1}")?;
+        }
+        for [r, g, b] in &self.table {
+            writeln!(f, "{r:.6} {g:.6} {b:.6}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for CubeLut {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut lut = Self {
+            title:  None,
+            size:   0,
+            is_3d:  true,
+            domain: DEFAULT_DOMAIN,
+            table:  Vec::new(),
+        };
+
+        for line in s.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words[0] {
+                "TITLE" => {
+                    lut.title = Some(line["TITLE".len() ..].trim().trim_matches('"').to_owned());
+                }
+                "LUT_1D_SIZE" | "LUT_3D_SIZE" => {
+                    let [size] = parse_floats::<1>(&words[1 ..], line)?;
+                    lut.size = size as usize;
+                    lut.is_3d = words[0] == "LUT_3D_SIZE";
+                }
+                "DOMAIN_MIN" => lut.domain[0] = parse_floats(&words[1 ..], line)?,
+                "DOMAIN_MAX" => lut.domain[1] = parse_floats(&words[1 ..], line)?,
+                "LUT_1D_INPUT_RANGE" | "LUT_3D_INPUT_RANGE" => {
+                    let [min, max] = parse_floats::<2>(&words[1 ..], line)?;
+                    lut.domain = [[min; 3], [max; 3]];
+                }
+                _ => lut.table.push(parse_floats(&words, line)?),
+            }
+        }
+
+        let expected = if lut.is_3d { lut.size.pow(3) } else { lut.size };
+        if lut.size < 2 {
+            error_message("A .cube file needs a LUT_1D_SIZE or LUT_3D_SIZE of at least 2")
+        } else if lut.table.len() != expected {
+            error_message(format!(
+                "Expected {expected} table entries for size {}, found {}",
+                lut.size,
+                lut.table.len()
+            ))
+        } else {
+            Ok(lut)
+        }
+    }
+}
+
+#[test]
+fn test_cube_lut() {
+    let identity = CubeLut {
+        title:  Some("identity".to_owned()),
+        size:   2,
+        is_3d:  true,
+        domain: DEFAULT_DOMAIN,
+        table:  (0 .. 8)
+            .map(|i| [(i & 1) as f32, ((i >> 1) & 1) as f32, (i >> 2) as f32])
+            .collect(),
+    };
+    let parsed: CubeLut = identity.to_string().parse().unwrap();
+    assert_eq!(parsed, identity);
+
+    let sample = identity.sample([0.25, 0.5, 0.75]);
+    assert!(sample.iter().zip([0.25, 0.5, 0.75]).all(|(a, b)| (a - b).abs() < 1e-6));
+
+    let resampled = identity.resample(5);
+    assert_eq!(resampled.table.len(), 125);
+    assert!((resampled.table[1][0] - 0.25).abs() < 1e-6);
+    assert!((resampled.table[5 * 5 * 2][2] - 0.5).abs() < 1e-6);
+
+    let curve: CubeLut = "# comment\nLUT_1D_SIZE 2\nLUT_1D_INPUT_RANGE 0 2\n0 0 0\n1 1 1\n"
+        .parse()
+        .unwrap();
+    assert!((curve.sample([1.0; 3])[1] - 0.5).abs() < 1e-6);
+    assert!("LUT_3D_SIZE 2\n0 0 0\n".parse::<CubeLut>().is_err());
+}
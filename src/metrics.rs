@@ -0,0 +1,82 @@
+//! How far a compressed texture is from the image it was compressed from.
+
+use std::fmt::Display;
+
+use windows::Win32::Graphics::Dxgi::Common::DXGI_FORMAT_R32G32B32A32_FLOAT;
+
+use crate::dxtex::DXImage;
+use crate::prelude::*;
+
+/// Below this the compression artifacts are usually easy to see
+pub const LOW_PSNR: f64 = 30.0;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CompressionError {
+    /// Root mean square error over all channels, in the `0..1` range
+    pub rmse: f64,
+    /// Peak signal to noise ratio in dB, infinite for identical images
+    pub psnr: f64,
+}
+
+impl CompressionError {
+    /// Compares every mip level and array slice of `compressed` with the
+    /// uncompressed `original`.
+    pub fn measure(original: &DXImage, compressed: &DXImage) -> Result<Self> {
+        let original = float_pixels(original)?;
+        let decompressed = float_pixels(&compressed.decompress()?)?;
+
+        if original.len() != decompressed.len() {
+            return error_message("Internal error: compared images of different sizes");
+        }
+
+        Ok(Self::from_pixels(&original, &decompressed))
+    }
+
+    #[must_use]
+    pub fn from_pixels(original: &[f32], compressed: &[f32]) -> Self {
+        let squared: f64 = original
+            .iter()
+            .zip(compressed)
+            .map(|(a, b)| f64::from(a - b).powi(2))
+            .sum();
+        let rmse = (squared / original.len().max(1) as f64).sqrt();
+        let psnr = if rmse > 0.0 {
+            -20.0 * rmse.log10()
+        } else {
+            f64::INFINITY
+        };
+
+        Self { rmse, psnr }
+    }
+
+    #[must_use]
+    pub fn is_low_quality(&self) -> bool { self.psnr < LOW_PSNR }
+}
+
+fn float_pixels(image: &DXImage) -> Result<Vec<f32>> {
+    let float = image.to_format(DXGI_FORMAT_R32G32B32A32_FLOAT)?;
+
+    Ok(float
+        .pixels()?
+        .chunks_exact(4)
+        .map(|bytes| f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect())
+}
+
+impl Display for CompressionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "RMSE {:.4}, PSNR {:.1} dB", self.rmse, self.psnr)
+    }
+}
+
+#[test]
+fn test_compression_error() {
+    let same = CompressionError::from_pixels(&[0.5, 1.0], &[0.5, 1.0]);
+    assert_eq!(same.rmse, 0.0);
+    assert!(same.psnr.is_infinite());
+
+    let off = CompressionError::from_pixels(&[0.0, 0.0, 0.0, 0.0], &[0.1, 0.1, 0.1, 0.1]);
+    assert!((off.rmse - 0.1).abs() < 1e-6);
+    assert!((off.psnr - 20.0).abs() < 1e-4);
+    assert!(off.is_low_quality());
+}
@@ -0,0 +1,255 @@
+//! Hand made mip levels. They come from a DDS file that already has the whole
+//! chain, or from `name@mip1.png`, `name@mip2.png`... next to `name.png` and
+//! in the same image format, so that they are grouped with it. Array slices
+//! use `name#01@mip1.png`. Exports write the game's own mip levels with the
+//! same names, so that they can be painted over and imported again.
+
+use camino::{Utf8Path, Utf8PathBuf};
+
+use crate::dxtex::{DXImage, TexMetadata};
+use crate::prelude::*;
+
+pub const MIP_TAG: &str = "@mip";
+
+/// The mip level of a `name@mipN` file, `None` for anything else.
+#[must_use]
+pub fn mip_level(file: &Utf8Path) -> Option<u8> {
+    let stem = file.file_name()?.split('.').next()?;
+    let (_, level) = stem.rsplit_once(MIP_TAG)?;
+
+    level.parse().ok().filter(|level| *level > 0)
+}
+
+/// `name` for `name@mipN`, the name unchanged otherwise.
+#[must_use]
+pub fn strip_mip_tag(name: &str) -> &str {
+    match name.rsplit_once(MIP_TAG) {
+        Some((base, level)) if level.parse::<u8>().is_ok() => base,
+        _ => name,
+    }
+}
+
+/// The file holding mip `level` for `image_file`, with the same extension.
+#[must_use]
+pub fn mip_file(image_file: &Utf8Path, level: u8) -> Utf8PathBuf {
+    let name = image_file.file_name().unwrap_or_default();
+    let (stem, ext) = name.split_once('.').unwrap_or((name, ""));
+
+    image_file.with_file_name(format!("{stem}{MIP_TAG}{level}.{ext}"))
+}
+
+/// Separates the mip level files of a group from its images.
+#[must_use]
+pub fn partition(files: &[Utf8PathBuf]) -> (Vec<Utf8PathBuf>, Vec<Utf8PathBuf>) {
+    files.iter().cloned().partition(|file| mip_level(file).is_none())
+}
+
+/// The mip level files for `image_file`, in level order, among `mip_files`.
+pub fn mip_files_for<'a>(
+    image_file: &Utf8Path,
+    mip_files: &'a [Utf8PathBuf],
+) -> Vec<(u8, &'a Utf8PathBuf)> {
+    let stem = image_file
+        .file_name()
+        .and_then(|name| name.split('.').next())
+        .unwrap_or_default();
+
+    let mut found: Vec<(u8, &Utf8PathBuf)> = mip_files
+        .iter()
+        .filter(|file| file.parent() == image_file.parent())
+        .filter_map(|file| {
+            let level = mip_level(file)?;
+            let name = file.file_name()?.split('.').next()?;
+            (strip_mip_tag(name) == stem).then_some((level, file))
+        })
+        .collect();
+    found.sort_by_key(|(level, _)| *level);
+    found
+}
+
+/// The width and height of mip `level` of a chain starting at `dimensions`.
+#[must_use]
+pub const fn level_size(dimensions: Dimensions, level: u8) -> (usize, usize) {
+    let width = dimensions.width >> level;
+    let height = dimensions.height >> level;
+
+    (if width > 0 { width } else { 1 }, if height > 0 { height } else { 1 })
+}
+
+/// The level of a chain described by `metadata` that is the size of
+/// `dimensions`.
+fn find_level(metadata: &TexMetadata, dimensions: Dimensions) -> Option<usize> {
+    let top = Dimensions {
+        width: metadata.width,
+        height: metadata.height,
+        ..dimensions
+    };
+
+    (0 .. metadata.mipLevels)
+        .find(|&level| level_size(top, level as u8) == (dimensions.width, dimensions.height))
+}
+
+/// Why an image in the texture's own format, described by `metadata`, can't
+/// be stored as every texture of `format` without encoding it again. `None`
+/// when the array size matches and each texture is a run of its mip levels.
+#[must_use]
+pub fn layout_mismatch(format: &TextureFormat, metadata: &TexMetadata) -> Option<String> {
+    if metadata.arraySize != format.array_size {
+        return Some(format!(
+            "it has {} array slices, the texture has {}",
+            metadata.arraySize, format.array_size
+        ));
+    }
+
+    let levels = metadata.mipLevels;
+    format.dimensions_iter().find_map(|dimensions| {
+        let (width, height) = (dimensions.width, dimensions.height);
+        let mipmaps = usize::from(dimensions.mipmaps);
+
+        match find_level(metadata, dimensions) {
+            None => {
+                let top = Dimensions {
+                    width: metadata.width,
+                    height: metadata.height,
+                    ..dimensions
+                };
+                let (last_width, last_height) = level_size(top, levels.max(1) as u8 - 1);
+                Some(format!(
+                    "its mip levels go from {}x{} to {last_width}x{last_height}, none is \
+                     {width}x{height}",
+                    metadata.width, metadata.height
+                ))
+            }
+            Some(first) if first + mipmaps > levels => Some(format!(
+                "it has {} mip levels from {width}x{height}, the texture needs {mipmaps}",
+                levels - first
+            )),
+            Some(_) => None,
+        }
+    })
+}
+
+/// The reverse of [`sub_chain`]: the levels of `top` followed by the levels
+/// of `rest` that are smaller than its last one. Both need the same format and
+/// array size, and `rest` has to continue where `top` stops.
+pub fn join_chain(top: &DXImage, rest: &DXImage) -> Result<DXImage> {
+    let (upper, lower) = (top.metadata()?, rest.metadata()?);
+    if upper.format != lower.format || upper.arraySize != lower.arraySize {
+        return error_message("The textures of the mip chain have different formats");
+    }
+
+    let first = Dimensions {
+        data_size: 0,
+        width:     upper.width,
+        height:    upper.height,
+        mipmaps:   1,
+    };
+    let (width, height) = level_size(first, upper.mipLevels as u8);
+    let next = Dimensions {
+        width,
+        height,
+        ..first
+    };
+    let from = find_level(&lower, next).ok_or_else(|| {
+        Error::message(format!("The smaller texture has no {width}x{height} mip level"))
+    })?;
+
+    let mut buffer = Vec::new();
+    for slice in 0 .. upper.arraySize {
+        for level in 0 .. upper.mipLevels {
+            buffer.extend(top.image(slice * upper.mipLevels + level)?);
+        }
+        for level in from .. lower.mipLevels {
+            buffer.extend(rest.image(slice * lower.mipLevels + level)?);
+        }
+    }
+
+    let levels = upper.mipLevels + lower.mipLevels - from;
+    DXImage::with_dimensions(
+        upper.format,
+        Dimensions {
+            mipmaps: levels as u8,
+            ..first
+        },
+        upper.arraySize,
+        &buffer,
+    )
+}
+
+/// Takes the part of a full mip chain that starts at `dimensions`, for a
+/// texture whose lower resolution version is part of the high resolution one.
+/// Returns `None` when no level has the right size.
+pub fn sub_chain(chain: &DXImage, dimensions: Dimensions) -> Result<Option<DXImage>> {
+    let metadata = chain.metadata()?;
+    let levels = metadata.mipLevels;
+
+    let first = match find_level(&metadata, dimensions) {
+        Some(first) if first + usize::from(dimensions.mipmaps) <= levels => first,
+        _ => return Ok(None),
+    };
+
+    let mut buffer = Vec::new();
+    for slice in 0 .. metadata.arraySize {
+        for level in first .. first + usize::from(dimensions.mipmaps) {
+            buffer.extend(chain.image(slice * levels + level)?);
+        }
+    }
+
+    DXImage::with_dimensions(metadata.format, dimensions, metadata.arraySize, &buffer).map(Some)
+}
+
+#[test]
+fn test_mip_names() {
+    let file = Utf8Path::new("dir/wall_c#01.png");
+
+    assert_eq!(mip_level(file), None);
+    assert_eq!(mip_file(file, 2), Utf8PathBuf::from("dir/wall_c#01@mip2.png"));
+    assert_eq!(mip_level(&mip_file(file, 2)), Some(2));
+    assert_eq!(strip_mip_tag("wall_c#01@mip2"), "wall_c#01");
+    assert_eq!(strip_mip_tag("wall_c#01"), "wall_c#01");
+
+    let mips = [
+        Utf8PathBuf::from("dir/wall_c#01@mip2.tga"),
+        Utf8PathBuf::from("dir/wall_c#02@mip1.png"),
+        Utf8PathBuf::from("dir/wall_c#01@mip1.png"),
+    ];
+    let found = mip_files_for(file, &mips);
+    assert_eq!(found, [(1, &mips[2]), (2, &mips[0])]);
+
+    let dimensions = Dimensions {
+        data_size: 0,
+        width:     256,
+        height:    64,
+        mipmaps:   9,
+    };
+    assert_eq!(level_size(dimensions, 1), (128, 32));
+    assert_eq!(level_size(dimensions, 8), (1, 1));
+
+    let format: TextureFormat = serde_json::from_str(
+        r#"{"dxgi_format": 98,
+            "standard": {"data_size": 0, "width": 512, "height": 512, "mipmaps": 10},
+            "highres": {"data_size": 0, "width": 1024, "height": 1024, "mipmaps": 11}}"#,
+    )
+    .unwrap();
+    let metadata = |width, array_size, levels| TexMetadata {
+        width,
+        height: width,
+        arraySize: array_size,
+        mipLevels: levels,
+        ..TexMetadata::default()
+    };
+
+    assert_eq!(layout_mismatch(&format, &metadata(1024, 1, 11)), None);
+    assert_eq!(
+        layout_mismatch(&format, &metadata(1024, 6, 11)).unwrap(),
+        "it has 6 array slices, the texture has 1"
+    );
+    assert_eq!(
+        layout_mismatch(&format, &metadata(1024, 1, 9)).unwrap(),
+        "it has 9 mip levels from 1024x1024, the texture needs 11"
+    );
+    assert_eq!(
+        layout_mismatch(&format, &metadata(2048, 1, 1)).unwrap(),
+        "its mip levels go from 2048x2048 to 2048x2048, none is 1024x1024"
+    );
+}
@@ -0,0 +1,206 @@
+//! Where exported images go and what they are called. A name template such as
+//! `{suffix}/{base}_{width}` is filled in for every image and placed in the
+//! output directory, or next to the texture. Arrays that are saved as one file
+//! per slice get `#{slice}` added when the template doesn't place it, so that
+//! the slices are grouped again on import.
+
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+use windows::Win32::Graphics::Dxgi::Common::DXGI_FORMAT_BC7_UNORM;
+
+use crate::files::base_name;
+use crate::formats::ImageFormat;
+use crate::options::ExportOptions;
+use crate::prelude::*;
+
+pub const DEFAULT_TEMPLATE: &str = "{base}";
+pub const PLACEHOLDERS: [&str; 6] = ["base", "slice", "suffix", "format", "width", "height"];
+
+/// The values of the placeholders for one image. Spaces in the format name
+/// become underscores.
+#[derive(Debug, Clone)]
+pub struct NameFields<'a> {
+    /// The texture name without directory and extension
+    pub base:   &'a str,
+    /// 1-based, `None` for textures saved as one file
+    pub slice:  Option<usize>,
+    pub format: DXGI_FORMAT,
+    /// The largest size of the texture
+    pub width:  usize,
+    pub height: usize,
+}
+
+impl NameFields<'_> {
+    /// The `c`, `n`, `g`... type suffix of the name, empty without one.
+    #[must_use]
+    pub fn suffix(&self) -> &str {
+        self.base
+            .rsplit_once('_')
+            .map_or("", |(_, suffix)| suffix)
+    }
+}
+
+/// Fills in the placeholders of `template`.
+pub fn render(template: &str, fields: &NameFields<'_>) -> Result<String> {
+    let mut name = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        name.push_str(&rest[.. start]);
+        let end = rest[start ..].find('}').map(|end| start + end).ok_or_else(|| {
+            Error::message(format!("The name template {template} has an unclosed {{"))
+        })?;
+
+        match &rest[start + 1 .. end] {
+            "base" => name.push_str(fields.base),
+            "slice" => {
+                if let Some(slice) = fields.slice {
+                    name.push_str(&format!("{slice:02}"));
+                }
+            }
+            "suffix" => name.push_str(fields.suffix()),
+            "format" => name.push_str(&fields.format.display().to_string().replace(' ', "_")),
+            "width" => name.push_str(&fields.width.to_string()),
+            "height" => name.push_str(&fields.height.to_string()),
+            placeholder => {
+                return error_message(format!(
+                    "Unknown placeholder {{{placeholder}}} in the name template, the known ones \
+                     are {{{}}}",
+                    PLACEHOLDERS.join("}, {")
+                ));
+            }
+        }
+        rest = &rest[end + 1 ..];
+    }
+    name.push_str(rest);
+
+    Ok(name)
+}
+
+/// Checks a template before anything is exported with it. Dots are reserved
+/// for the extension, which is added to every name.
+pub fn check_template(template: &str) -> Result<()> {
+    if template.trim().is_empty() {
+        return error_message("The name template is empty");
+    }
+    if template.contains('.') {
+        return error_message(format!(
+            "The name template {template} can't contain dots, the extension is added to it"
+        ));
+    }
+
+    render(template, &NameFields {
+        base:   "name",
+        slice:  Some(1),
+        format: DXGI_FORMAT_BC7_UNORM,
+        width:  1,
+        height: 1,
+    })
+    .map(|_| ())
+}
+
+/// Names are always placed inside the output directory, so separators left at
+/// the start by empty placeholders are dropped. Anything else that isn't a
+/// relative file name is an error.
+fn relative_name(name: &str) -> Result<&str> {
+    let name = name.trim_start_matches(std::path::is_separator);
+    let relative = !name.is_empty()
+        && !name.ends_with(std::path::is_separator)
+        && Utf8Path::new(name)
+            .components()
+            .all(|part| matches!(part, Utf8Component::Normal(_)));
+
+    if relative {
+        Ok(name)
+    } else {
+        error_message(format!("The name template renders {name:?}, which isn't a file name"))
+    }
+}
+
+/// The images a texture in `format` is exported to as `image_format`. With
+/// the default options these are the files [`crate::files::as_images`] names.
+pub fn export_files(
+    format: &TextureFormat,
+    image_format: ImageFormat,
+    texture_files: &[Utf8PathBuf],
+    options: &ExportOptions,
+) -> Result<Vec<Utf8PathBuf>> {
+    let first = texture_files
+        .first()
+        .ok_or_else(|| Error::message("Internal error: no texture to name the images after"))?;
+    let base_path = Utf8Path::new(base_name(first));
+    let directory = options
+        .directory
+        .as_deref()
+        .or_else(|| base_path.parent())
+        .unwrap_or_else(|| Utf8Path::new(""));
+
+    let template = options.name_template.as_deref().unwrap_or(DEFAULT_TEMPLATE);
+    check_template(template)?;
+
+    let ext = image_format
+        .extensions_str()
+        .first()
+        .copied()
+        .ok_or_else(|| Error::message("Internal error: the image format has no extension"))?;
+    let one_file = format.num_images() == 1 || image_format.can_save_array();
+    let slices = if one_file { 1 } else { format.num_images() };
+    let template = if one_file || template.contains("{slice}") {
+        template.to_owned()
+    } else {
+        format!("{template}#{{slice}}")
+    };
+
+    let dimensions = format.dimensions();
+    (1 ..= slices)
+        .map(|slice| {
+            let name = render(&template, &NameFields {
+                base:   base_path.file_name().unwrap_or_default(),
+                slice:  (!one_file).then_some(slice),
+                format: format.dxgi_format,
+                width:  dimensions.width,
+                height: dimensions.height,
+            })?;
+            Ok(directory.join(format!("{}.{ext}", relative_name(&name)?)))
+        })
+        .collect()
+}
+
+#[test]
+fn test_render_names() {
+    use windows::Win32::Graphics::Dxgi::Common::DXGI_FORMAT_BC1_UNORM_SRGB;
+
+    let fields = NameFields {
+        base:   "leaves_c",
+        slice:  Some(3),
+        format: DXGI_FORMAT_BC1_UNORM_SRGB,
+        width:  2048,
+        height: 1024,
+    };
+
+    assert_eq!(fields.suffix(), "c");
+    assert_eq!(
+        render("{suffix}/{base}#{slice}_{width}x{height}", &fields).unwrap(),
+        "c/leaves_c#03_2048x1024"
+    );
+    let single = NameFields {
+        slice: None,
+        ..fields.clone()
+    };
+    assert_eq!(render(DEFAULT_TEMPLATE, &single).unwrap(), "leaves_c");
+    assert_eq!(render("{base}_{format}", &single).unwrap(), "leaves_c_BC1_sRGB");
+    assert!(render("{base", &fields).is_err());
+    assert!(render("{name}", &fields).is_err());
+
+    let untyped = NameFields {
+        base: "leaves",
+        ..single
+    };
+    let name = render("{suffix}/{base}", &untyped).unwrap();
+    assert_eq!(relative_name(&name).unwrap(), "leaves");
+    assert!(relative_name(&render("{base}/{suffix}", &untyped).unwrap()).is_err());
+    assert!(relative_name("").is_err());
+
+    assert!(check_template("{base}_{width}").is_ok());
+    assert!(check_template("{base}.png").is_err());
+    assert!(check_template(" ").is_err());
+}
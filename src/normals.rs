@@ -0,0 +1,186 @@
+//! Normal maps need their vectors kept at unit length, which generic
+//! resizing, mip generation and sRGB conversion all break.
+//!
+//! The work is done on `R32G32B32A32_FLOAT` images in the encoded `0..1`
+//! range the textures store, with X, Y and Z in red, green and blue.
+
+use std::borrow::Cow;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use windows::Win32::Graphics::Dxgi::Common::DXGI_FORMAT_R32G32B32A32_FLOAT;
+
+use crate::dxtex::{DXImage, TEX_FILTER_FLAGS};
+use crate::files::base_name;
+use crate::options::{NormalMapMode, NormalMapOptions};
+use crate::prelude::*;
+use crate::sidecar::{sidecar_file, Sidecar};
+
+pub const NORMAL_MAP_SUFFIX: &str = "_n";
+pub const FLOAT_FORMAT: DXGI_FORMAT = DXGI_FORMAT_R32G32B32A32_FLOAT;
+
+/// Whether a group of files should be treated as a normal map, from the
+/// options, the metadata file or the file name suffix.
+#[must_use]
+pub fn is_normal_map(files: &[Utf8PathBuf], options: &NormalMapOptions) -> bool {
+    match options.mode {
+        NormalMapMode::Always => true,
+        NormalMapMode::Never => false,
+        NormalMapMode::Detect => files.first().map_or(false, |file| {
+            Sidecar::load(&sidecar_file(file)).map_or(false, |sidecar| sidecar.normal_map)
+                || has_normal_map_suffix(file)
+        }),
+    }
+}
+
+#[must_use]
+pub fn has_normal_map_suffix(file: &Utf8Path) -> bool {
+    Utf8Path::new(base_name(file))
+        .file_name()
+        .map_or(false, |name| name.ends_with(NORMAL_MAP_SUFFIX))
+}
+
+/// Turns a resized float image into the full mip chain of `format`,
+/// renormalizing every level and skipping any sRGB conversion. Images that
+/// already have the whole chain keep it.
+pub fn import(
+    image: &DXImage,
+    format: DXGI_FORMAT,
+    mipmaps: u8,
+    options: &NormalMapOptions,
+) -> Result<DXImage> {
+    // Hand made mip levels are kept
+    let mipmapped;
+    let image = if mipmaps > 1 && image.metadata()?.mipLevels < usize::from(mipmaps) {
+        mipmapped = image.generate_mipmaps(mipmaps)?;
+        &mipmapped
+    } else {
+        image
+    };
+
+    let image = map_pixels(image, |pixel| {
+        if options.flip_green {
+            flip_green(pixel);
+        }
+        renormalize(pixel);
+    })?;
+
+    let linear = format.linear_format();
+    let image = image.convert(linear, TEX_FILTER_FLAGS::default())?;
+    if linear != format {
+        image.override_format(format)?;
+    }
+
+    Ok(image)
+}
+
+/// Applies the export options to a decoded normal map. The result is always
+/// stored without the sRGB curve.
+pub fn export<'a>(image: &'a DXImage, options: &NormalMapOptions) -> Result<Cow<'a, DXImage>> {
+    if !options.flip_green && !options.reconstruct_z {
+        return Ok(Cow::Borrowed(image));
+    }
+
+    let metadata = image.metadata()?;
+    let float = if metadata.format.is_srgb() {
+        let linear = image.clone();
+        linear.override_format(metadata.format.linear_format())?;
+        linear.convert(FLOAT_FORMAT, TEX_FILTER_FLAGS::default())?
+    } else {
+        image.convert(FLOAT_FORMAT, TEX_FILTER_FLAGS::default())?
+    };
+
+    let float = map_pixels(&float, |pixel| {
+        if options.reconstruct_z {
+            reconstruct_z(pixel);
+        }
+        if options.flip_green {
+            flip_green(pixel);
+        }
+    })?;
+
+    Ok(Cow::Owned(float.convert(
+        metadata.format.linear_format(),
+        TEX_FILTER_FLAGS::default(),
+    )?))
+}
+
+/// Runs `func` on every pixel of every mip level and array slice of a float
+/// image.
+fn map_pixels(image: &DXImage, func: impl Fn(&mut [f32])) -> Result<DXImage> {
+    let metadata = image.metadata()?;
+    if metadata.format != FLOAT_FORMAT {
+        return error_message("Internal error: normal maps are processed as float images");
+    }
+
+    let mut pixels: Vec<f32> = image
+        .pixels()?
+        .chunks_exact(4)
+        .map(|bytes| f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect();
+    pixels.chunks_exact_mut(4).for_each(func);
+
+    DXImage::with_dimensions(
+        FLOAT_FORMAT,
+        Dimensions {
+            data_size: 0,
+            width:     metadata.width,
+            height:    metadata.height,
+            mipmaps:   metadata.mipLevels as u8,
+        },
+        metadata.arraySize,
+        bytemuck::cast_slice(&pixels),
+    )
+}
+
+fn decode(value: f32) -> f32 { value * 2.0 - 1.0 }
+
+fn encode(value: f32) -> f32 { (value + 1.0) / 2.0 }
+
+/// Scales the vector in an RGBA pixel back to unit length. Vectors that
+/// collapsed to nothing point straight up.
+pub fn renormalize(pixel: &mut [f32]) {
+    let (x, y, z) = (decode(pixel[0]), decode(pixel[1]), decode(pixel[2]));
+    let length = (x * x + y * y + z * z).sqrt();
+
+    if length > f32::EPSILON {
+        pixel[0] = encode(x / length);
+        pixel[1] = encode(y / length);
+        pixel[2] = encode(z / length);
+    } else {
+        pixel[0] = 0.5;
+        pixel[1] = 0.5;
+        pixel[2] = 1.0;
+    }
+}
+
+/// Converts between OpenGL (Y up) and DirectX (Y down) normal maps.
+pub fn flip_green(pixel: &mut [f32]) { pixel[1] = 1.0 - pixel[1]; }
+
+/// Rebuilds Z from X and Y, for formats that only store two channels.
+pub fn reconstruct_z(pixel: &mut [f32]) {
+    let (x, y) = (decode(pixel[0]), decode(pixel[1]));
+    pixel[2] = encode((1.0 - x * x - y * y).max(0.0).sqrt());
+}
+
+#[test]
+fn test_normal_pixels() {
+    let mut pixel = [1.0, 0.5, 1.0, 1.0];
+    renormalize(&mut pixel);
+    let (x, z) = (decode(pixel[0]), decode(pixel[2]));
+    assert!((x * x + z * z - 1.0).abs() < 1e-5);
+
+    let mut pixel = [0.5, 0.5, 0.5, 1.0];
+    renormalize(&mut pixel);
+    assert_eq!(pixel, [0.5, 0.5, 1.0, 1.0]);
+
+    let mut pixel = [0.5, 0.5, 0.0, 1.0];
+    reconstruct_z(&mut pixel);
+    assert!((pixel[2] - 1.0).abs() < 1e-5);
+
+    let mut pixel = [0.5, 0.25, 1.0, 1.0];
+    flip_green(&mut pixel);
+    assert!((pixel[1] - 0.75).abs() < 1e-5);
+
+    assert!(has_normal_map_suffix(Utf8Path::new("dir/wall_n#01.png")));
+    assert!(!has_normal_map_suffix(Utf8Path::new("dir/wall_c.png")));
+}
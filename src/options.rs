@@ -0,0 +1,692 @@
+//! Settings that change how files are detected and converted, shared by the
+//! command line tool and the GUI.
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use camino::Utf8PathBuf;
+use image::imageops::FilterType;
+use serde::{Deserialize, Serialize};
+
+use crate::channels::ChannelRecipe;
+use crate::dxtex::{TEX_COMPRESS_FLAGS, TEX_FILTER_FLAGS};
+use crate::formats::ImageFormat;
+use crate::prelude::*;
+
+/// Options that affect the converted files are saved in the metadata file
+/// written with every export.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Options {
+    /// Report where each detected format came from
+    #[serde(skip)]
+    pub explain: bool,
+    pub import:  ImportOptions,
+    pub export:  ExportOptions,
+    pub color:   ColorOptions,
+    pub normals: NormalMapOptions,
+    /// How cubemaps are stored in images, in both directions
+    pub cubemap: CubeLayout,
+    /// Store texture arrays as one grid image with this many columns, in both
+    /// directions
+    pub atlas:   Option<usize>,
+    pub tonemap: ToneMapOptions,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImportOptions {
+    pub resize_filter:   ResizeFilter,
+    /// What to do with images that don't have the aspect ratio of the texture
+    pub aspect:          AspectMode,
+    /// Build each image from channels of other images
+    pub pack:            Option<ChannelRecipe>,
+    pub quality:         CompressionQuality,
+    /// Report the compression error of every texture, not only bad ones
+    pub report_error:    bool,
+    /// Import at the size of the images and rewrite the texture header to
+    /// match, instead of resizing to the size of the texture
+    pub keep_resolution: bool,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportOptions {
+    /// Also write every channel as a greyscale image
+    pub split_channels: bool,
+    /// Also write every mip level below the first as a `name@mipN` image
+    pub mip_levels:     bool,
+    /// Write one DDS file with the texture data and its whole mip chain
+    /// instead of images
+    pub dds_chain:      bool,
+    pub image_format:   ExportFormat,
+    /// Save the images here instead of next to the textures
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub directory:      Option<Utf8PathBuf>,
+    /// Names the images, see [`crate::naming`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name_template:  Option<String>,
+}
+
+/// How the colours of images relate to the texture, in both directions.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColorOptions {
+    /// The colour space of the images
+    pub space: ColorSpace,
+    /// Applied to imported images before resizing and to exported textures
+    /// before saving
+    pub alpha: AlphaMode,
+}
+
+/// How HDR textures are turned into LDR previews.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToneMapOptions {
+    pub operator: ToneMapOperator,
+    /// In stops, applied before the operator
+    pub exposure: f32,
+    /// Also write a tone mapped PNG next to every exported HDR image
+    pub preview:  bool,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NormalMapOptions {
+    pub mode:          NormalMapMode,
+    /// Flip the green channel between the OpenGL and DirectX conventions, in
+    /// both directions
+    pub flip_green:    bool,
+    /// Rebuild the blue channel from red and green when exporting
+    pub reconstruct_z: bool,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NormalMapMode {
+    /// Use the metadata file or the `_n` file name suffix
+    #[default]
+    Detect,
+    Always,
+    Never,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColorSpace {
+    /// The same as the texture format, which passes the values through
+    #[default]
+    Auto,
+    Srgb,
+    Linear,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AlphaMode {
+    #[default]
+    Keep,
+    /// Multiply the colour by the alpha
+    Premultiply,
+    /// Divide premultiplied colour by the alpha
+    Unpremultiply,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CubeLayout {
+    /// One file per face
+    #[default]
+    Faces,
+    HorizontalCross,
+    VerticalCross,
+    /// All faces in a row
+    Strip,
+    /// Latitude and longitude
+    Equirect,
+}
+
+/// The container exported images are saved in.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExportFormat {
+    /// EXR for HDR textures, DDS for arrays of 6 or more, PNG for BC1 and TGA
+    /// for the rest
+    #[default]
+    Auto,
+    Png,
+    Tga,
+    Dds,
+    Exr,
+    /// Lossless, 8 bits per channel
+    Webp,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ToneMapOperator {
+    /// Exposure only, values above 1 are clipped
+    Clamp,
+    Reinhard,
+    /// Krzysztof Narkowicz's fit of the ACES filmic curve
+    #[default]
+    AcesFitted,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CompressionQuality {
+    Draft,
+    #[default]
+    Normal,
+    Max,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResizeFilter {
+    /// DirectXTex picks the filter, image-rs uses Lanczos
+    #[default]
+    Auto,
+    Point,
+    Linear,
+    Cubic,
+    Box,
+    Triangle,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AspectMode {
+    #[default]
+    Stretch,
+    /// Fit the image inside the texture and fill the rest with an RGBA colour
+    Pad([u8; 4]),
+    /// Cut the edges off to fill the texture
+    Crop,
+    /// Refuse to import
+    Reject,
+}
+
+impl ResizeFilter {
+    pub const ALL: [Self; 6] = [
+        Self::Auto,
+        Self::Point,
+        Self::Linear,
+        Self::Cubic,
+        Self::Box,
+        Self::Triangle,
+    ];
+
+    #[must_use]
+    pub const fn dxtex(self) -> TEX_FILTER_FLAGS {
+        match self {
+            Self::Auto => TEX_FILTER_FLAGS(0),
+            Self::Point => TEX_FILTER_FLAGS::TEX_FILTER_POINT,
+            Self::Linear => TEX_FILTER_FLAGS::TEX_FILTER_LINEAR,
+            Self::Cubic => TEX_FILTER_FLAGS::TEX_FILTER_CUBIC,
+            Self::Box => TEX_FILTER_FLAGS::TEX_FILTER_BOX,
+            Self::Triangle => TEX_FILTER_FLAGS::TEX_FILTER_TRIANGLE,
+        }
+    }
+
+    /// The closest image-rs filter, which has no box filter.
+    #[must_use]
+    pub const fn imagers(self) -> FilterType {
+        match self {
+            Self::Auto => FilterType::Lanczos3,
+            Self::Point => FilterType::Nearest,
+            Self::Linear | Self::Box | Self::Triangle => FilterType::Triangle,
+            Self::Cubic => FilterType::CatmullRom,
+        }
+    }
+}
+
+impl CubeLayout {
+    pub const ALL: [Self; 5] = [
+        Self::Faces,
+        Self::HorizontalCross,
+        Self::VerticalCross,
+        Self::Strip,
+        Self::Equirect,
+    ];
+}
+
+impl ExportFormat {
+    pub const ALL: [Self; 6] = [
+        Self::Auto,
+        Self::Png,
+        Self::Tga,
+        Self::Dds,
+        Self::Exr,
+        Self::Webp,
+    ];
+
+    /// The image format for a texture in `format`, `None` when it can't hold
+    /// the texture's values. HDR textures need EXR or DDS.
+    #[must_use]
+    pub fn image_format(self, format: &TextureFormat) -> Option<ImageFormat> {
+        let image_format = match self {
+            Self::Auto => return Some(format.default_image_format()),
+            Self::Png => ImageFormat::Png,
+            Self::Tga => ImageFormat::Tga,
+            Self::Dds => ImageFormat::Dds,
+            Self::Exr => ImageFormat::OpenExr,
+            Self::Webp => ImageFormat::WebP,
+        };
+
+        let keeps_range = matches!(image_format, ImageFormat::Dds | ImageFormat::OpenExr);
+        (keeps_range || !format.dxgi_format.is_hdr()).then_some(image_format)
+    }
+}
+
+impl ExportOptions {
+    /// The chosen image format, or the default one if it doesn't fit `format`.
+    #[must_use]
+    pub fn image_format_for(&self, format: &TextureFormat) -> ImageFormat {
+        self.image_format
+            .image_format(format)
+            .unwrap_or_else(|| format.default_image_format())
+    }
+}
+
+impl ToneMapOperator {
+    pub const ALL: [Self; 3] = [Self::Clamp, Self::Reinhard, Self::AcesFitted];
+}
+
+impl CompressionQuality {
+    pub const ALL: [Self; 3] = [Self::Draft, Self::Normal, Self::Max];
+    /// Presets other than `Normal` need the `compress-ex` feature
+    pub const SUPPORTED: bool = cfg!(feature = "compress-ex");
+
+    /// Only used with the `compress-ex` feature. The BC7 flags are ignored by
+    /// the other formats.
+    #[must_use]
+    pub fn dxtex(self) -> TEX_COMPRESS_FLAGS {
+        let parallel = TEX_COMPRESS_FLAGS::TEX_COMPRESS_PARALLEL;
+        match self {
+            Self::Draft => parallel | TEX_COMPRESS_FLAGS::TEX_COMPRESS_BC7_QUICK,
+            Self::Normal => parallel,
+            Self::Max => parallel | TEX_COMPRESS_FLAGS::TEX_COMPRESS_BC7_USE_3SUBSETS,
+        }
+    }
+}
+
+impl AspectMode {
+    pub const DEFAULT_PAD: [u8; 4] = [0, 0, 0, 255];
+}
+
+impl NormalMapMode {
+    pub const ALL: [Self; 3] = [Self::Detect, Self::Always, Self::Never];
+}
+
+impl ColorSpace {
+    pub const ALL: [Self; 3] = [Self::Auto, Self::Srgb, Self::Linear];
+
+    /// Whether images hold sRGB data for a texture in `format`.
+    #[must_use]
+    pub fn is_srgb(self, format: DXGI_FORMAT) -> bool {
+        match self {
+            Self::Auto => format.is_srgb(),
+            Self::Srgb => true,
+            Self::Linear => false,
+        }
+    }
+}
+
+impl AlphaMode {
+    pub const ALL: [Self; 3] = [Self::Keep, Self::Premultiply, Self::Unpremultiply];
+}
+
+impl Display for ResizeFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Auto => "auto",
+            Self::Point => "point",
+            Self::Linear => "linear",
+            Self::Cubic => "cubic",
+            Self::Box => "box",
+            Self::Triangle => "triangle",
+        })
+    }
+}
+
+impl FromStr for ResizeFilter {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|filter| filter.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| Error::message(format!("Unknown resize filter {s}")))
+    }
+}
+
+impl Display for CubeLayout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Faces => "faces",
+            Self::HorizontalCross => "horizontal-cross",
+            Self::VerticalCross => "vertical-cross",
+            Self::Strip => "strip",
+            Self::Equirect => "equirect",
+        })
+    }
+}
+
+impl FromStr for CubeLayout {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|layout| layout.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| Error::message(format!("Unknown cubemap layout {s}")))
+    }
+}
+
+impl Display for ExportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Auto => "auto",
+            Self::Png => "png",
+            Self::Tga => "tga",
+            Self::Dds => "dds",
+            Self::Exr => "exr",
+            Self::Webp => "webp",
+        })
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|image_format| image_format.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| Error::message(format!("Unknown export image format {s}")))
+    }
+}
+
+impl Display for ToneMapOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Clamp => "clamp",
+            Self::Reinhard => "reinhard",
+            Self::AcesFitted => "aces",
+        })
+    }
+}
+
+impl FromStr for ToneMapOperator {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|operator| operator.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| Error::message(format!("Unknown tone mapping operator {s}")))
+    }
+}
+
+impl Display for CompressionQuality {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Draft => "draft",
+            Self::Normal => "normal",
+            Self::Max => "max",
+        })
+    }
+}
+
+impl FromStr for CompressionQuality {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|quality| quality.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| Error::message(format!("Unknown compression quality {s}")))
+    }
+}
+
+impl Display for NormalMapMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Detect => "detect",
+            Self::Always => "always",
+            Self::Never => "never",
+        })
+    }
+}
+
+impl FromStr for NormalMapMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|mode| mode.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| Error::message(format!("Unknown normal map mode {s}")))
+    }
+}
+
+impl Display for ColorSpace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Auto => "auto",
+            Self::Srgb => "srgb",
+            Self::Linear => "linear",
+        })
+    }
+}
+
+impl FromStr for ColorSpace {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|space| space.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| Error::message(format!("Unknown colour space {s}")))
+    }
+}
+
+impl Display for AlphaMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Keep => "keep",
+            Self::Premultiply => "premultiply",
+            Self::Unpremultiply => "unpremultiply",
+        })
+    }
+}
+
+impl FromStr for AlphaMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|mode| mode.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| Error::message(format!("Unknown alpha mode {s}")))
+    }
+}
+
+impl Display for AspectMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Stretch => f.write_str("stretch"),
+            Self::Pad(color) => write!(f, "pad={}", hex::encode(color)),
+            Self::Crop => f.write_str("crop"),
+            Self::Reject => f.write_str("reject"),
+        }
+    }
+}
+
+impl FromStr for AspectMode {
+    type Err = Error;
+
+    /// `stretch`, `crop`, `reject` or `pad`, optionally followed by `=RRGGBB`
+    /// or `=RRGGBBAA`.
+    fn from_str(s: &str) -> Result<Self> {
+        let (mode, color) = s.split_once('=').unwrap_or((s, ""));
+
+        match (mode.to_ascii_lowercase().as_str(), color.len()) {
+            ("stretch", 0) => Ok(Self::Stretch),
+            ("crop", 0) => Ok(Self::Crop),
+            ("reject", 0) => Ok(Self::Reject),
+            ("pad", 0) => Ok(Self::Pad(Self::DEFAULT_PAD)),
+            ("pad", 6 | 8) => {
+                let mut rgba = Self::DEFAULT_PAD;
+                let bytes = hex::decode(color)?;
+                rgba[.. bytes.len()].copy_from_slice(&bytes);
+                Ok(Self::Pad(rgba))
+            }
+            _ => error_message(format!("Unknown aspect ratio mode {s}")),
+        }
+    }
+}
+
+impl Options {
+    /// Splits `--option` arguments from file arguments. Everything after a
+    /// bare `--` is treated as a file.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<(Self, Vec<Utf8PathBuf>)> {
+        let mut options = Self::default();
+        let mut files = Vec::new();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--" => files.extend(args.by_ref().map(Utf8PathBuf::from)),
+                "--explain" => options.explain = true,
+                "--resize-filter" => {
+                    options.import.resize_filter = value_for(&arg, &mut args)?.parse()?;
+                }
+                "--aspect" => options.import.aspect = value_for(&arg, &mut args)?.parse()?,
+                "--pack" => options.import.pack = Some(value_for(&arg, &mut args)?.parse()?),
+                "--quality" if !CompressionQuality::SUPPORTED => {
+                    return error_message("--quality needs a build with the compress-ex feature");
+                }
+                "--quality" => options.import.quality = value_for(&arg, &mut args)?.parse()?,
+                "--report-error" => options.import.report_error = true,
+                "--keep-resolution" => options.import.keep_resolution = true,
+                "--split-channels" => options.export.split_channels = true,
+                "--mip-levels" => options.export.mip_levels = true,
+                "--dds-chain" => options.export.dds_chain = true,
+                "--image-format" => {
+                    options.export.image_format = value_for(&arg, &mut args)?.parse()?;
+                }
+                "--output-dir" => {
+                    options.export.directory = Some(value_for(&arg, &mut args)?.into());
+                }
+                "--name-template" => {
+                    let template = value_for(&arg, &mut args)?;
+                    crate::naming::check_template(&template)?;
+                    options.export.name_template = Some(template);
+                }
+                "--color-space" => options.color.space = value_for(&arg, &mut args)?.parse()?,
+                "--alpha" => options.color.alpha = value_for(&arg, &mut args)?.parse()?,
+                "--cubemap" => options.cubemap = value_for(&arg, &mut args)?.parse()?,
+                "--atlas" => {
+                    let columns = value_for(&arg, &mut args)?;
+                    options.atlas = Some(columns.parse().map_err(|_| {
+                        Error::message(format!("--atlas needs a number of columns, got {columns}"))
+                    })?);
+                }
+                "--tonemap" => options.tonemap.operator = value_for(&arg, &mut args)?.parse()?,
+                "--exposure" => {
+                    let stops = value_for(&arg, &mut args)?;
+                    options.tonemap.exposure = stops.parse().map_err(|_| {
+                        Error::message(format!("--exposure needs a number of stops, got {stops}"))
+                    })?;
+                }
+                "--hdr-preview" => options.tonemap.preview = true,
+                "--normal-map" => options.normals.mode = value_for(&arg, &mut args)?.parse()?,
+                "--flip-green" => options.normals.flip_green = true,
+                "--reconstruct-z" => options.normals.reconstruct_z = true,
+                option if option.starts_with("--") => {
+                    return error_message(format!("Unknown option {option}"));
+                }
+                _ => files.push(Utf8PathBuf::from(arg)),
+            }
+        }
+
+        Ok((options, files))
+    }
+
+    pub fn from_args() -> Result<(Self, Vec<Utf8PathBuf>)> { Self::parse(std::env::args().skip(1)) }
+}
+
+fn value_for(option: &str, args: &mut impl Iterator<Item = String>) -> Result<String> {
+    args.next()
+        .ok_or_else(|| Error::message(format!("{option} needs a value")))
+}
+
+#[test]
+fn test_parse_options() {
+    let args = ["a.texture", "--explain", "--", "--b.png"].map(String::from);
+    let (options, files) = Options::parse(args).unwrap();
+
+    assert!(options.explain);
+    assert_eq!(files, [Utf8PathBuf::from("a.texture"), Utf8PathBuf::from("--b.png")]);
+    assert!(Options::parse(["--nope".to_string()]).is_err());
+
+    let args = ["--resize-filter", "Cubic", "--aspect", "pad=ff0000", "--pack", "split"]
+        .map(String::from);
+    let (options, _) = Options::parse(args).unwrap();
+
+    assert_eq!(options.import.pack, Some(ChannelRecipe::split()));
+    assert_eq!(options.import.quality, CompressionQuality::Normal);
+
+    assert_eq!(options.import.resize_filter, ResizeFilter::Cubic);
+    assert_eq!(options.import.aspect, AspectMode::Pad([255, 0, 0, 255]));
+    assert_eq!(
+        options.import.aspect.to_string().parse::<AspectMode>().unwrap(),
+        options.import.aspect
+    );
+
+    let args = ["--color-space", "linear", "--alpha", "Premultiply"].map(String::from);
+    let (options, _) = Options::parse(args).unwrap();
+
+    let quality = Options::parse(["--quality", "max"].map(String::from));
+    if CompressionQuality::SUPPORTED {
+        assert_eq!(quality.unwrap().0.import.quality, CompressionQuality::Max);
+    } else {
+        assert!(quality.is_err());
+    }
+    assert!(!options.import.keep_resolution);
+    assert!(Options::parse(["--keep-resolution".to_string()]).unwrap().0.import.keep_resolution);
+    assert_eq!(options.color.space, ColorSpace::Linear);
+    assert_eq!(options.color.alpha, AlphaMode::Premultiply);
+
+    let args = ["--cubemap", "vertical-cross", "--atlas", "4"].map(String::from);
+    let (options, _) = Options::parse(args).unwrap();
+
+    assert_eq!(options.cubemap, CubeLayout::VerticalCross);
+    assert_eq!(options.atlas, Some(4));
+    assert!(Options::parse(["--atlas", "four"].map(String::from)).is_err());
+
+    let args = ["--split-channels", "--mip-levels", "--dds-chain"].map(String::from);
+    let (options, _) = Options::parse(args).unwrap();
+
+    assert_eq!(options.export, ExportOptions {
+        split_channels: true,
+        mip_levels: true,
+        dds_chain: true,
+        ..ExportOptions::default()
+    });
+
+    let args = ["--image-format", "EXR", "--output-dir", "out"];
+    let args = args.into_iter().chain(["--name-template", "{suffix}/{base}"]).map(String::from);
+    let (options, _) = Options::parse(args).unwrap();
+
+    assert_eq!(options.export.image_format, ExportFormat::Exr);
+    assert_eq!(options.export.directory, Some(Utf8PathBuf::from("out")));
+    assert_eq!(options.export.name_template.as_deref(), Some("{suffix}/{base}"));
+    let args = ["--image-format", "webp"].map(String::from);
+    assert_eq!(Options::parse(args).unwrap().0.export.image_format, ExportFormat::Webp);
+    assert!(Options::parse(["--image-format", "gif"].map(String::from)).is_err());
+    assert!(Options::parse(["--name-template", "{base}.png"].map(String::from)).is_err());
+
+    let args = ["--tonemap", "Reinhard", "--exposure", "-1.5", "--hdr-preview"].map(String::from);
+    let (options, _) = Options::parse(args).unwrap();
+
+    assert_eq!(options.tonemap, ToneMapOptions {
+        operator: ToneMapOperator::Reinhard,
+        exposure: -1.5,
+        preview:  true,
+    });
+}
@@ -1,13 +1,24 @@
 use std::collections::{BTreeMap, BTreeSet, HashSet};
-use std::fmt::{Debug, Display};
+use std::fmt::Debug;
 
 use camino::{Utf8Path, Utf8PathBuf};
 use serde::{Deserialize, Serialize};
 
-use crate::formats::{Source, TextureFormat};
+use crate::formats::TextureFormat;
 use crate::prelude::*;
 use crate::util::{current_dir_utf8, exe_dir_utf8, MaybeReady};
 
+mod check;
+pub use check::IntegrityIssue;
+mod id;
+pub use id::{FormatId, IdCollision};
+mod query;
+pub use query::{results_to_json, results_to_table, FormatQuery, QueryResult};
+mod scan;
+pub use scan::{guess_array_size, ChangedEntry, DiffEntry, ScanDiff, ScanReport};
+mod subset;
+pub use subset::FormatSelection;
+
 static REGISTRY: MaybeReady<Registry> = MaybeReady::new();
 
 pub fn load() -> Result<()> {
@@ -75,7 +86,12 @@ pub struct Registry {
     #[serde(default)]
     pub examples:    BTreeMap<FormatId, String>,
     #[serde(default)]
-    pub suffixes: BTreeMap<String, BTreeSet<FormatId>>
+    pub suffixes: BTreeMap<String, BTreeSet<FormatId>>,
+    /// Version 1 ids of every format, see [`FormatId::legacy`]
+    #[serde(skip)]
+    pub legacy_ids:  BTreeMap<FormatId, FormatId>,
+    #[serde(skip)]
+    pub collisions:  Vec<IdCollision>,
 }
 
 impl Registry {
@@ -103,26 +119,71 @@ impl Registry {
 
     #[inline]
     #[must_use]
-    pub fn known(&self, id: impl FormatRef) -> bool { self.formats.contains_key(id.as_ref()) }
+    pub fn known(&self, id: impl FormatRef) -> bool {
+        self.formats.contains_key(&self.resolve(*id.as_ref()))
+    }
 
     #[inline]
     #[must_use]
     pub fn get(&self, id: impl FormatRef) -> &TextureFormat {
         self.formats
-            .get(id.as_ref())
+            .get(&self.resolve(*id.as_ref()))
             .unwrap_or_else(|| panic!("Failed to resolve format {id:?}"))
     }
 
     #[inline]
     #[must_use]
-    pub fn try_get(&self, id: FormatId) -> Option<&TextureFormat> { self.formats.get(&id) }
+    pub fn try_get(&self, id: FormatId) -> Option<&TextureFormat> {
+        self.formats.get(&self.resolve(id))
+    }
 
     #[inline]
     #[must_use]
     pub fn raw_header(&self, id: impl FormatRef) -> Option<String> {
-        self.raw_headers.get(id.as_ref()).map(Clone::clone)
+        self.raw_headers
+            .get(&self.resolve(*id.as_ref()))
+            .map(Clone::clone)
+    }
+
+    /// The raw header of a format. Formats that weren't read from a header
+    /// don't know its flags, so the header of a format with the same layout
+    /// is used for those.
+    #[must_use]
+    pub fn raw_header_for(&self, format: &TextureFormat) -> Option<&str> {
+        let id = format.id();
+        let id = if self.raw_headers.contains_key(&id) || format.stex_format != (0, 0) {
+            id
+        } else {
+            let unflagged = |known: &TextureFormat| TextureFormat {
+                stex_format: (0, 0),
+                ..*known
+            };
+            self.formats
+                .iter()
+                .find(|(_, known)| unflagged(known).same_layout(format))
+                .map_or(id, |(id, _)| *id)
+        };
+
+        self.raw_headers.get(&id).map(String::as_str)
     }
 
+    /// Maps a version 1 id to the current id of its format, current ids are
+    /// returned unchanged.
+    #[inline]
+    #[must_use]
+    pub fn resolve(&self, id: FormatId) -> FormatId {
+        if id.is_legacy() {
+            self.legacy_ids.get(&id).copied().unwrap_or(id)
+        } else {
+            id
+        }
+    }
+
+    /// Id collisions found while loading or updating the registry.
+    #[inline]
+    #[must_use]
+    pub fn collisions(&self) -> &[IdCollision] { &self.collisions }
+
     #[inline]
     #[must_use]
     pub fn get_all<'r, ID>(&'r self, ids: impl IntoIterator<Item = ID>) -> Vec<&'r TextureFormat>
@@ -134,33 +195,21 @@ impl Registry {
     #[inline]
     pub fn load() -> Result<Self> { Ok(Self::default()) }
 
-    #[cfg(disabled)]
-    pub fn get_override(&self, file: &Utf8Path) -> Option<TextureFormat> {
-        None
+    /// The first override whose pattern matches the file name.
+    #[must_use]
+    pub fn override_for(&self, file: &Utf8Path) -> Option<(&str, FormatId)> {
+        let name = file.file_name()?;
+
+        self.overrides
+            .iter()
+            .find(|(pattern, _)| {
+                regex::Regex::new(pattern)
+                    .log_failure_with(|| format!("Invalid override pattern {pattern}"))
+                    .map_or(false, |regex| regex.is_match(name))
+            })
+            .map(|(pattern, id)| (pattern.as_str(), self.resolve(*id)))
     }
 
-    // #[must_use]
-    // pub fn get_override(&self, file: &Utf8Path) -> Option<TextureFormat> {
-    //     let size = std::fs::metadata(file).ok()?.len() as usize;
-    //     let file_stem = file.file_stem()?;
-
-    //     if file_stem.ends_with("_g") {
-    //         event!(DEBUG, ?file_stem, ?size);
-    //     }
-
-    //     let format = if file_stem.ends_with("_g") && size == 327_680 {
-    //         Some(registry::get(FormatId(0x800_aa62)))
-    //     } else {
-    //         None
-    //     };
-
-    //     let format = *format?;
-    //     Some(TextureFormat {
-    //         source: Source::FromFilename,
-    //         ..format
-    //     })
-    // }
-
     pub fn make_ref(&mut self, format: TextureFormat) -> &TextureFormat {
         let id = format.id();
         if !self.known(id) {
@@ -172,6 +221,7 @@ impl Registry {
     #[cfg(not(feature = "rebuild-registry"))]
     pub fn load() -> Result<Self> {
         let mut registry: Self = serde_json::from_str(Self::EMBEDDED).log_failure()?;
+        registry.migrate_legacy_ids();
 
         if let Some(dir) = current_dir_utf8() {
             registry.try_extend_from_dir(&dir);
@@ -180,6 +230,16 @@ impl Registry {
             registry.try_extend_from_dir(&dir);
         }
 
+        registry.index_legacy_ids();
+        for collision in &registry.collisions {
+            event!(WARN, "Format id collision: {collision}");
+        }
+
+        #[cfg(debug_assertions)]
+        for issue in registry.check_integrity() {
+            event!(WARN, "Registry: {issue}");
+        }
+
         Ok(registry)
     }
 
@@ -190,19 +250,118 @@ impl Registry {
     }
 
     pub fn extend(&mut self, other: Self) {
-        self.formats.extend(other.formats);
+        for (id, format) in other.formats {
+            self.insert_checked(id, format);
+        }
 
         for (len, ids) in other.lengths {
             self.lengths.entry(len).or_default().extend(ids);
         }
+        for (suffix, ids) in other.suffixes {
+            self.suffixes.entry(suffix).or_default().extend(ids);
+        }
+        for (id, header) in other.raw_headers {
+            self.raw_headers.entry(id).or_insert(header);
+        }
+        for (id, example) in other.examples {
+            self.examples.entry(id).or_insert(example);
+        }
+        self.overrides.extend(other.overrides);
+        self.collisions.extend(other.collisions);
+    }
+
+    /// Inserts a format unless its id is taken, in which case a different
+    /// layout under the same id is recorded as a collision.
+    fn insert_checked(&mut self, id: FormatId, format: TextureFormat) {
+        match self.formats.get(&id) {
+            None => {
+                self.formats.insert(id, format);
+            }
+            Some(existing) if existing.same_layout(&format) => (),
+            Some(existing) => self.collisions.push(IdCollision::Conflict {
+                id,
+                existing: *existing,
+                incoming: format,
+            }),
+        }
+    }
+
+    /// Re-keys every format stored under an outdated id. Version 1 registries
+    /// don't store the header flags, so they are recovered from the raw
+    /// headers where possible.
+    fn migrate_legacy_ids(&mut self) {
+        let mut remap: BTreeMap<FormatId, FormatId> = BTreeMap::new();
+
+        for (stored, mut format) in std::mem::take(&mut self.formats) {
+            if stored.is_legacy() {
+                if let Some(header) = self
+                    .raw_headers
+                    .get(&stored)
+                    .and_then(|hex| texture_file::FormatHeader::from_hexstring(hex).ok())
+                {
+                    format.stex_format = (header.stex_format, header.planes);
+                }
+            }
+
+            let id = format.id();
+            if stored != id {
+                if !stored.is_legacy() {
+                    self.collisions.push(IdCollision::Mismatch {
+                        stored,
+                        computed: id,
+                    });
+                }
+                remap.insert(stored, id);
+            }
+            self.insert_checked(id, format);
+        }
+
+        if remap.is_empty() {
+            return;
+        }
+
+        let map_id = |id: &FormatId| remap.get(id).copied().unwrap_or(*id);
+
+        for ids in self.lengths.values_mut().chain(self.suffixes.values_mut()) {
+            *ids = ids.iter().map(map_id).collect();
+        }
+        for (_, id) in &mut self.overrides {
+            *id = map_id(id);
+        }
+        for map in [&mut self.raw_headers, &mut self.examples] {
+            *map = std::mem::take(map)
+                .into_iter()
+                .map(|(id, value)| (map_id(&id), value))
+                .collect();
+        }
+    }
+
+    /// Rebuilds the version 1 lookup table, recording legacy ids shared by
+    /// more than one format.
+    fn index_legacy_ids(&mut self) {
+        let mut shared: BTreeMap<FormatId, Vec<FormatId>> = BTreeMap::new();
+
+        for (id, format) in &self.formats {
+            shared
+                .entry(FormatId::legacy(format))
+                .or_default()
+                .push(*id);
+        }
+
+        self.legacy_ids.clear();
+        for (legacy, ids) in shared {
+            self.legacy_ids.insert(legacy, ids[0]);
+            if ids.len() > 1 {
+                self.collisions.push(IdCollision::Legacy { legacy, ids });
+            }
+        }
     }
 
     pub fn update_length(&mut self, length: usize, id: FormatId) {
         self.lengths.entry(length).or_default().insert(id);
     }
 
-    pub fn update_header(&mut self, header: &texture_file::FormatHeader) {
-        let id = TextureFormat::from(header).id();
+    pub fn update_header(&mut self, id: FormatId, header: &texture_file::FormatHeader) {
         self.raw_headers.insert(id, header.as_hexstring());
     }
 
@@ -254,9 +413,17 @@ impl Registry {
             self.update_length(hd_len, id);
         }
 
-        if self.known(id) {
+        if let Some(existing) = self.formats.get(&id) {
             #[cfg(feature = "debug-imports")]
             event!(TRACE, ?id, ?format, "Format already known");
+            if !existing.same_layout(&format) {
+                event!(WARN, "{id} already used by {existing}, not adding {format}");
+                self.collisions.push(IdCollision::Conflict {
+                    id,
+                    existing: *existing,
+                    incoming: format,
+                });
+            }
         } else {
             #[cfg(feature = "debug-imports")]
             event!(TRACE, ?id, ?format, "Inserting format");
@@ -271,20 +438,19 @@ impl Registry {
         id
     }
 
-    // TODO: add a variant to save specific formats only
     pub fn save(&mut self) -> Result<()> {
-        let file = Utf8Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/formats.json"));
-        let json =
-            serde_json::to_string_pretty(self).log_failure_as("Failed to serialize registry")?;
-        std::fs::write(file, &json).log_failure_as("Failed to save registry")?;
-
-        Ok(())
+        self.save_as(concat!(env!("CARGO_MANIFEST_DIR"), "/formats.json"))
     }
 }
 
+/// Loads a registry file, re-keying version 1 ids so that it can be merged
+/// with the embedded registry.
 fn load_format_file(file: impl AsRef<Utf8Path>) -> Result<Registry> {
     let fmt = std::fs::read(file.as_ref())?;
-    Ok(serde_json::from_slice(&fmt).log_failure()?)
+    let mut registry: Registry = serde_json::from_slice(&fmt).log_failure()?;
+    registry.migrate_legacy_ids();
+
+    Ok(registry)
 }
 
 fn try_format_file_name(dir: impl AsRef<Utf8Path>) -> Option<Utf8PathBuf> {
@@ -297,59 +463,3 @@ fn try_format_file_name(dir: impl AsRef<Utf8Path>) -> Option<Utf8PathBuf> {
         None
     }
 }
-
-#[derive(Debug, Hash, PartialEq, Eq, Ord, PartialOrd, Copy, Clone)]
-pub struct FormatId(u64);
-
-impl AsRef<Self> for FormatId {
-    fn as_ref(&self) -> &Self { self }
-}
-
-impl Display for FormatId {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "FormatId({:016X})", self.0)
-    }
-}
-
-impl Serialize for FormatId {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where S: serde::Serializer {
-        serializer.serialize_str(&format!("{:09x}", self.0))
-    }
-}
-
-impl<'de> Deserialize<'de> for FormatId {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where D: serde::Deserializer<'de> {
-        <&str>::deserialize(deserializer).and_then(|s| {
-            u64::from_str_radix(s, 16)
-                .map_err(serde::de::Error::custom)
-                .map(FormatId)
-        })
-    }
-}
-
-impl From<&TextureFormat> for FormatId {
-    #[cfg_attr(feature = "debug-formats", instrument(ret))]
-    fn from(format: &TextureFormat) -> Self {
-        const FORMAT_BITS: u32 = 7;
-        const DIM_BITS: u32 = 14;
-        const SIZE_BITS: u32 = 40;
-
-        /* 0.. 6 */
-        let mut hash: u64 = u64::from(format.dxgi_format.0);
-        /* 7..20 */
-        hash |= (format.standard.width as u64) << FORMAT_BITS;
-        /* 21..34 */
-        hash |= (format.standard.height as u64) << (FORMAT_BITS + DIM_BITS);
-        /* 35..64 */
-        hash |= (format.standard.data_size as u64).reverse_bits() >> SIZE_BITS;
-
-        Self(hash)
-
-        // Self(
-        //     (u64::from(format.dxgi_format.0)) << u32::BITS
-        //         | u64::from(crc32fast::hash(format.raw_headers.as_ref())),
-        // )
-    }
-}
@@ -0,0 +1,175 @@
+use std::fmt::Display;
+
+use super::{FormatId, Registry};
+use crate::prelude::*;
+
+/// A registry entry that doesn't agree with itself, found by
+/// [`Registry::check_integrity`].
+#[derive(Debug, Clone)]
+pub enum IntegrityIssue {
+    /// A stored data size differs from the size DirectXTex computes for the
+    /// dimensions, mip count and array size
+    DataSize {
+        id:       FormatId,
+        texture:  &'static str,
+        stored:   usize,
+        expected: usize,
+    },
+    /// A field is wider than its `.texture` header field, so the id is
+    /// truncated
+    BadId {
+        id:    FormatId,
+        error: String,
+    },
+    MissingHeader {
+        id: FormatId,
+    },
+    BadHeader {
+        id:    FormatId,
+        error: String,
+    },
+    /// The raw header decodes to a different format
+    HeaderMismatch {
+        id:      FormatId,
+        decoded: TextureFormat,
+    },
+    UnknownFormat {
+        length: usize,
+        id:     FormatId,
+    },
+    /// A format is listed under a length that none of its textures have
+    WrongLength {
+        length: usize,
+        id:     FormatId,
+    },
+}
+
+impl Display for IntegrityIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DataSize {
+                id,
+                texture,
+                stored,
+                expected,
+            } => write!(
+                f,
+                "{id}: {texture} data size is {stored}, expected {expected}"
+            ),
+            Self::BadId { id, error } => write!(f, "{id}: {error}"),
+            Self::MissingHeader { id } => write!(f, "{id}: No raw header"),
+            Self::BadHeader { id, error } => write!(f, "{id}: Invalid raw header: {error}"),
+            Self::HeaderMismatch { id, decoded } => write!(
+                f,
+                "{id}: Raw header decodes to {decoded} ({})",
+                decoded.id()
+            ),
+            Self::UnknownFormat { length, id } => {
+                write!(f, "Length {length} lists unknown format {id}")
+            }
+            Self::WrongLength { length, id } => {
+                write!(f, "Length {length} lists {id}, which has no texture of that size")
+            }
+        }
+    }
+}
+
+impl Registry {
+    /// Checks that data sizes match the texture layouts, that raw headers
+    /// decode back to their formats and that the length index is accurate.
+    #[must_use]
+    pub fn check_integrity(&self) -> Vec<IntegrityIssue> {
+        let mut issues = Vec::new();
+
+        for (id, format) in &self.formats {
+            let textures = [("standard", Some(format.standard)), ("highres", format.highres)];
+
+            for (texture, dimensions) in textures
+                .into_iter()
+                .filter_map(|(texture, dims)| dims.map(|dims| (texture, dims)))
+            {
+                let expected =
+                    dxtex::expected_size_array(format.dxgi_format, dimensions, format.array_size);
+
+                if dimensions.data_size != expected {
+                    issues.push(IntegrityIssue::DataSize {
+                        id: *id,
+                        texture,
+                        stored: dimensions.data_size,
+                        expected,
+                    });
+                }
+            }
+
+            if let Err(error) = FormatId::checked(format) {
+                issues.push(IntegrityIssue::BadId {
+                    id:    *id,
+                    error: error.to_string(),
+                });
+            }
+
+            match self.raw_headers.get(id) {
+                None => issues.push(IntegrityIssue::MissingHeader { id: *id }),
+                Some(hex) => match texture_file::FormatHeader::from_hexstring(hex) {
+                    Err(error) => issues.push(IntegrityIssue::BadHeader {
+                        id:    *id,
+                        error: error.to_string(),
+                    }),
+                    Ok(header) => {
+                        let decoded = header.to();
+                        if !decoded.same_layout(format) {
+                            issues.push(IntegrityIssue::HeaderMismatch { id: *id, decoded });
+                        }
+                    }
+                },
+            }
+        }
+
+        for (length, ids) in &self.lengths {
+            for id in ids {
+                match self.try_get(*id) {
+                    None => issues.push(IntegrityIssue::UnknownFormat {
+                        length: *length,
+                        id:     *id,
+                    }),
+                    Some(format) => {
+                        if format.dimensions_for_size(*length).is_none() {
+                            issues.push(IntegrityIssue::WrongLength {
+                                length: *length,
+                                id:     *id,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        issues
+    }
+}
+
+#[test]
+fn test_check_bad_id() {
+    use windows::Win32::Graphics::Dxgi::Common::DXGI_FORMAT_BC7_UNORM;
+
+    let format = TextureFormat {
+        dxgi_format: DXGI_FORMAT_BC7_UNORM,
+        standard:    Dimensions {
+            data_size: 0,
+            width:     1024,
+            height:    1024,
+            mipmaps:   1,
+        },
+        highres:     None,
+        array_size:  0,
+        stex_format: (0, 0),
+        source:      Default::default(),
+    };
+    let mut registry = Registry::default();
+    registry.formats.insert(format.id(), format);
+
+    let issues = registry.check_integrity();
+    assert!(issues
+        .iter()
+        .any(|issue| matches!(issue, IntegrityIssue::BadId { id, .. } if *id == format.id())));
+}
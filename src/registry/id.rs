@@ -0,0 +1,283 @@
+//! Format ids, version 2.
+//!
+//! A [`FormatId`] packs every field that identifies a texture layout into a
+//! `u128`, so two formats only share an id when they are the same layout:
+//!
+//! ```text
+//!   0 ..  16  DXGI format
+//!  16 ..  32  standard width
+//!  32 ..  48  standard height
+//!  48 ..  56  standard mip count
+//!  56 ..  64  stex format header byte
+//!  64 ..  72  planes header byte
+//!  72 ..  88  high-resolution width (0 without a high-resolution texture)
+//!  88 .. 104  high-resolution height
+//! 104 .. 112  high-resolution mip count
+//! 112 .. 128  array size
+//! ```
+//!
+//! Every field is stored at the width it has in the `.texture` header, so
+//! nothing can overflow into its neighbour, and values that don't fit are
+//! rejected by [`FormatId::checked`]. Data sizes are left out because they
+//! follow from the fields above, and the registry checks them separately.
+//! Array sizes start at 1, so the upper 64 bits are never all zero, which is
+//! what tells version 2 ids apart from version 1 ids.
+//!
+//! Version 1 ids packed the DXGI format into 7 bits, the standard dimensions
+//! into 14 bits each and the bit-reversed standard data size into the rest.
+//! They are still accepted everywhere an id is read and are mapped to their
+//! version 2 id when a registry is loaded, see [`FormatId::legacy`].
+
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+
+#[derive(Debug, Hash, PartialEq, Eq, Ord, PartialOrd, Copy, Clone)]
+pub struct FormatId(u128);
+
+impl FormatId {
+    const LEGACY_BITS: u32 = 64;
+
+    /// The version 1 id of a format, only used to resolve ids from older
+    /// registries and tools.
+    #[must_use]
+    pub fn legacy(format: &TextureFormat) -> Self {
+        const FORMAT_BITS: u32 = 7;
+        const DIM_BITS: u32 = 14;
+        const SIZE_BITS: u32 = 40;
+
+        /* 0.. 6 */
+        let mut hash: u64 = u64::from(format.dxgi_format.0);
+        /* 7..20 */
+        hash |= (format.standard.width as u64) << FORMAT_BITS;
+        /* 21..34 */
+        hash |= (format.standard.height as u64) << (FORMAT_BITS + DIM_BITS);
+        /* 35..64 */
+        hash |= (format.standard.data_size as u64).reverse_bits() >> SIZE_BITS;
+
+        Self(u128::from(hash))
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn is_legacy(self) -> bool { self.0 >> Self::LEGACY_BITS == 0 }
+
+    pub fn from_hex(hex: &str) -> Result<Self> {
+        u128::from_str_radix(hex, 16)
+            .map(FormatId)
+            .map_err(|error| Error::message(format!("Invalid format id {hex}: {error}")))
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn to_hex(self) -> String {
+        if self.is_legacy() {
+            format!("{:09x}", self.0)
+        } else {
+            format!("{:032x}", self.0)
+        }
+    }
+}
+
+impl AsRef<Self> for FormatId {
+    fn as_ref(&self) -> &Self { self }
+}
+
+impl Display for FormatId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "FormatId({})", self.to_hex().to_ascii_uppercase())
+    }
+}
+
+impl Serialize for FormatId {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: serde::Serializer {
+        serializer.serialize_str(&self.to_hex())
+    }
+}
+
+impl<'de> Deserialize<'de> for FormatId {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where D: serde::Deserializer<'de> {
+        <&str>::deserialize(deserializer)
+            .and_then(|s| FormatId::from_hex(s).map_err(serde::de::Error::custom))
+    }
+}
+
+impl FormatId {
+    /// Packs the fields of a format, masking any that are too wide. The name
+    /// of the first field that didn't fit is returned alongside the id.
+    fn pack(format: &TextureFormat) -> (Self, Option<&'static str>) {
+        let highres = format.highres.unwrap_or(Dimensions {
+            data_size: 0,
+            width:     0,
+            height:    0,
+            mipmaps:   0,
+        });
+        let (stex_format, planes) = format.stex_format;
+
+        let fields: [(&str, u128, u32, u32); 10] = [
+            ("DXGI format", u128::from(format.dxgi_format.0), 16, 0),
+            ("width", format.standard.width as u128, 16, 16),
+            ("height", format.standard.height as u128, 16, 32),
+            ("mip count", u128::from(format.standard.mipmaps), 8, 48),
+            ("stex format", u128::from(stex_format), 8, 56),
+            ("planes", u128::from(planes), 8, 64),
+            ("high-resolution width", highres.width as u128, 16, 72),
+            ("high-resolution height", highres.height as u128, 16, 88),
+            ("high-resolution mip count", u128::from(highres.mipmaps), 8, 104),
+            ("array size", format.array_size as u128, 16, 112),
+        ];
+
+        let mut overflow = (format.array_size == 0).then_some("array size");
+        let mut id = 0;
+        for (name, value, bits, shift) in fields {
+            let mask = (1 << bits) - 1;
+            if value > mask {
+                overflow = overflow.or(Some(name));
+            }
+            id |= (value & mask) << shift;
+        }
+
+        (Self(id), overflow)
+    }
+
+    /// The id of a format, failing when a field doesn't fit its `.texture`
+    /// header field or the array size is 0.
+    pub fn checked(format: &TextureFormat) -> Result<Self> {
+        match Self::pack(format) {
+            (id, None) => Ok(id),
+            (_, Some(field)) => error_message(format!(
+                "{format}: The {field} doesn't fit in a format id"
+            )),
+        }
+    }
+}
+
+impl From<&TextureFormat> for FormatId {
+    /// Like [`FormatId::checked`], but masks fields that don't fit after
+    /// logging a warning.
+    #[cfg_attr(feature = "debug-formats", instrument(ret))]
+    fn from(format: &TextureFormat) -> Self {
+        let (id, overflow) = Self::pack(format);
+        if let Some(field) = overflow {
+            event!(WARN, "{format}: The {field} doesn't fit in a format id");
+        }
+        id
+    }
+}
+
+/// Two or more formats that ended up with the same id.
+#[derive(Debug, Clone)]
+pub enum IdCollision {
+    /// A different layout was added under an id that is already in use
+    Conflict {
+        id:       FormatId,
+        existing: TextureFormat,
+        incoming: TextureFormat,
+    },
+    /// A registry entry is stored under an id that doesn't match its format
+    Mismatch {
+        stored:   FormatId,
+        computed: FormatId,
+    },
+    /// Several formats share a version 1 id, so looking up that legacy id is
+    /// ambiguous
+    Legacy {
+        legacy: FormatId,
+        ids:    Vec<FormatId>,
+    },
+}
+
+impl Display for IdCollision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Conflict {
+                id,
+                existing,
+                incoming,
+            } => write!(
+                f,
+                "{id} is used by both {existing} ({:?}) and {incoming} ({:?})",
+                existing.standard, incoming.standard
+            ),
+            Self::Mismatch { stored, computed } => {
+                write!(f, "{stored} is stored under the wrong id, expected {computed}")
+            }
+            Self::Legacy { legacy, ids } => {
+                write!(f, "Legacy {legacy} is shared by {} formats:", ids.len())?;
+                for id in ids {
+                    write!(f, " {id}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[test]
+fn test_format_id_fields() {
+    use windows::Win32::Graphics::Dxgi::Common::{DXGI_FORMAT, DXGI_FORMAT_BC7_UNORM};
+
+    let standard = Dimensions {
+        data_size: 1_398_128,
+        width:     1024,
+        height:    1024,
+        mipmaps:   8,
+    };
+    let format = TextureFormat {
+        dxgi_format: DXGI_FORMAT_BC7_UNORM,
+        standard,
+        highres: None,
+        array_size: 1,
+        stex_format: (0, 0),
+        source: Default::default(),
+    };
+
+    let highres = TextureFormat {
+        highres: Some(Dimensions {
+            data_size: 5_592_512,
+            width: 4096,
+            height: 4096,
+            mipmaps: 2,
+        }),
+        ..format
+    };
+    let array = TextureFormat {
+        array_size: 6,
+        ..format
+    };
+    let flags = TextureFormat {
+        stex_format: (1, 0),
+        ..format
+    };
+
+    let ids = [format.id(), highres.id(), array.id(), flags.id()];
+    for (i, id) in ids.iter().enumerate() {
+        assert!(!id.is_legacy());
+        assert!(!ids[i + 1 ..].contains(id), "{id} collides");
+    }
+
+    assert_eq!(FormatId::legacy(&format), FormatId::legacy(&highres));
+    assert!(FormatId::legacy(&format).is_legacy());
+
+    let wide = TextureFormat {
+        dxgi_format: DXGI_FORMAT(0x100),
+        ..format
+    };
+    assert_ne!(wide.id(), format.id());
+    assert!(FormatId::checked(&wide).is_ok());
+
+    let empty = TextureFormat {
+        array_size: 0,
+        ..format
+    };
+    let too_wide = TextureFormat {
+        array_size: 0x1_0000,
+        ..format
+    };
+    assert!(FormatId::checked(&empty).is_err());
+    assert!(FormatId::checked(&too_wide).is_err());
+}
@@ -0,0 +1,153 @@
+use std::fmt::Write;
+
+use serde::Serialize;
+
+use super::{FormatId, Registry};
+use crate::prelude::*;
+
+/// Filters for browsing the registry. Every field that is set has to match
+/// for a format to be returned, unset fields match everything.
+#[derive(Debug, Default, Clone)]
+pub struct FormatQuery {
+    pub dxgi_format: Option<DXGI_FORMAT>,
+    /// Matches either the standard or the high-resolution width
+    pub width:       Option<usize>,
+    /// Matches either the standard or the high-resolution height
+    pub height:      Option<usize>,
+    pub array_size:  Option<usize>,
+    pub has_highres: Option<bool>,
+    /// Matches either the standard or the high-resolution mip count
+    pub mipmaps:     Option<u8>,
+    /// Matches the data size of either texture, with or without the header
+    pub data_size:   Option<usize>,
+    /// Case-insensitive substring of the example file name
+    pub example:     Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueryResult<'r> {
+    pub id:         FormatId,
+    pub name:       String,
+    #[serde(flatten)]
+    pub format:     &'r TextureFormat,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw_header: Option<&'r str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub example:    Option<&'r str>,
+}
+
+impl FormatQuery {
+    #[must_use]
+    pub fn matches(&self, format: &TextureFormat, example: Option<&str>) -> bool {
+        let any_dimension =
+            |test: &dyn Fn(Dimensions) -> bool| format.dimensions_iter().any(test);
+
+        self.dxgi_format
+            .map_or(true, |dxgi_format| dxgi_format == format.dxgi_format)
+            && self
+                .width
+                .map_or(true, |width| any_dimension(&|dims| dims.width == width))
+            && self
+                .height
+                .map_or(true, |height| any_dimension(&|dims| dims.height == height))
+            && self
+                .mipmaps
+                .map_or(true, |mipmaps| any_dimension(&|dims| dims.mipmaps == mipmaps))
+            && self
+                .data_size
+                .map_or(true, |size| any_dimension(&|dims| dims.is_for_file_size(size)))
+            && self
+                .array_size
+                .map_or(true, |array_size| array_size == format.array_size)
+            && self
+                .has_highres
+                .map_or(true, |has_highres| has_highres == format.has_highres())
+            && self.example.as_ref().map_or(true, |pattern| {
+                example.map_or(false, |example| {
+                    example
+                        .to_ascii_lowercase()
+                        .contains(&pattern.to_ascii_lowercase())
+                })
+            })
+    }
+}
+
+impl Registry {
+    #[must_use]
+    pub fn query(&self, query: &FormatQuery) -> Vec<QueryResult<'_>> {
+        self.formats
+            .iter()
+            .filter_map(|(id, format)| {
+                let example = self.examples.get(id).map(String::as_str);
+
+                query.matches(format, example).then(|| QueryResult {
+                    id: *id,
+                    name: format.to_string(),
+                    format,
+                    raw_header: self.raw_headers.get(id).map(String::as_str),
+                    example,
+                })
+            })
+            .collect()
+    }
+}
+
+pub fn results_to_json(results: &[QueryResult<'_>]) -> Result<String> {
+    Ok(serde_json::to_string_pretty(results)?)
+}
+
+#[must_use]
+pub fn results_to_table(results: &[QueryResult<'_>]) -> String {
+    const HEADINGS: [&str; 7] = [
+        "ID", "Format", "Standard", "Highres", "Array", "Example", "Header",
+    ];
+
+    let rows: Vec<[String; 7]> = results
+        .iter()
+        .map(|result| {
+            [
+                result.id.to_hex(),
+                result.format.dxgi_format.display().to_string(),
+                dimensions_cell(Some(result.format.standard)),
+                dimensions_cell(result.format.highres),
+                result.format.array_size.to_string(),
+                result.example.unwrap_or("-").to_string(),
+                result.raw_header.unwrap_or("-").to_string(),
+            ]
+        })
+        .collect();
+
+    let mut widths = HEADINGS.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = std::cmp::max(*width, cell.len());
+        }
+    }
+
+    let mut table = String::new();
+    push_row(&mut table, &widths, HEADINGS);
+    for row in &rows {
+        push_row(&mut table, &widths, row);
+    }
+
+    table
+}
+
+fn push_row<CELL: AsRef<str>>(
+    table: &mut String,
+    widths: &[usize],
+    cells: impl IntoIterator<Item = CELL>,
+) {
+    for (cell, width) in cells.into_iter().zip(widths.iter().copied()) {
+        let _ignored = write!(table, "{:<width$}  ", cell.as_ref());
+    }
+    table.truncate(table.trim_end().len());
+    table.push('\n');
+}
+
+fn dimensions_cell(dimensions: Option<Dimensions>) -> String {
+    dimensions.map_or_else(
+        || "-".to_string(),
+        |dims| format!("{dims} ({} bytes)", dims.data_size),
+    )
+}
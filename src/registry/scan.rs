@@ -0,0 +1,236 @@
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+
+use super::{FormatId, IdCollision, Registry};
+use crate::prelude::*;
+
+/// What a directory scan learned, compared to the registry it started from.
+#[derive(Debug, Default)]
+pub struct ScanReport {
+    /// Every format seen in the scan, with headers, lengths and examples
+    pub learned:   Registry,
+    /// Formats the base registry doesn't know
+    pub new:       Vec<FormatId>,
+    /// Known formats whose layout or raw header differs from the base
+    /// registry
+    pub changed:   Vec<FormatId>,
+    /// Files that produced different layouts under the same id
+    pub conflicts: Vec<IdCollision>,
+    /// Headerless files with a size the base registry doesn't know
+    pub unknown:   Vec<Utf8PathBuf>,
+    pub errors:    Vec<(Utf8PathBuf, String)>,
+    pub scanned:   usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScanDiff<'r> {
+    pub new:       Vec<DiffEntry<'r>>,
+    pub changed:   Vec<ChangedEntry<'r>>,
+    pub conflicts: Vec<String>,
+    pub unknown:   Vec<&'r str>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DiffEntry<'r> {
+    pub id:         FormatId,
+    pub format:     &'r TextureFormat,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw_header: Option<&'r str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub example:    Option<&'r str>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChangedEntry<'r> {
+    pub id:     FormatId,
+    pub before: DiffEntry<'r>,
+    pub after:  DiffEntry<'r>,
+}
+
+#[derive(Deserialize)]
+struct Override {
+    pattern: String,
+    header:  String,
+}
+
+impl Registry {
+    /// Scans a directory of extracted `.texture` and `.raw` files and learns
+    /// their formats. The registry itself is left untouched, use
+    /// [`ScanReport::updates`] to get the formats worth saving. Headerless
+    /// files matching one of the `overrides` are learned as its format.
+    pub fn scan(&self, dir: &Utf8Path, overrides: Option<&Utf8Path>) -> Result<ScanReport> {
+        let mut report = ScanReport::default();
+        if let Some(overrides) = overrides {
+            report.learned.load_overrides(overrides)?;
+        }
+
+        for file in crate::util::walkdir(dir).filter(|file| is_scannable(file)) {
+            let span = tracing::error_span!("", file = file.file_name().unwrap_or_default());
+            let _entered = span.enter();
+
+            report.scanned += 1;
+            if let Err(error) = self.scan_file(&file, &mut report) {
+                report.errors.push((file, error.to_string()));
+            }
+        }
+
+        for (id, format) in &report.learned.formats {
+            match self.formats.get(id) {
+                None => report.new.push(*id),
+                Some(known)
+                    if !known.same_layout(format)
+                        || self.raw_headers.get(id) != report.learned.raw_headers.get(id) =>
+                {
+                    report.changed.push(*id);
+                }
+                Some(_) => (),
+            }
+        }
+        report.conflicts = std::mem::take(&mut report.learned.collisions);
+
+        Ok(report)
+    }
+
+    fn scan_file(&self, file: &Utf8Path, report: &mut ScanReport) -> Result<()> {
+        let (header, data) = texture_file::read_texture(file)?;
+
+        let header = match header {
+            Some(header) => header,
+            None => {
+                let len = data.len();
+                let overridden = report
+                    .learned
+                    .override_for(file)
+                    .map(|(pattern, id)| (pattern.to_owned(), id));
+                if let Some((pattern, id)) = overridden {
+                    let format = report.learned.get(id);
+                    if format.dimensions_for_size(len).is_none() {
+                        return error_message(format!(
+                            "Matches override {pattern}, but {format} doesn't fit {len} bytes"
+                        ));
+                    }
+                    report.learned.update_length(len, id);
+                } else if self.lengths.get(&len).map_or(true, |ids| ids.is_empty()) {
+                    event!(WARN, "Unrecognized file with no header");
+                    report.unknown.push(file.to_owned());
+                }
+                return Ok(());
+            }
+        };
+
+        let mut format = header.to();
+        let data = format.without_header(&data);
+
+        if let Some(array_size) = guess_array_size(&format, data.len()) {
+            format.array_size = array_size;
+        }
+
+        let expected =
+            dxtex::expected_size_array(format.dxgi_format, format.standard, format.array_size);
+        if data.len() != expected {
+            return error_message(format!(
+                "{format}: Data size {} doesn't match expected size {expected}",
+                data.len()
+            ));
+        }
+
+        let id = report.learned.update_format(format, Some(file));
+        report.learned.update_header(id, &header);
+
+        Ok(())
+    }
+
+    /// Reads a list of `{ "pattern", "header" }` file name overrides, and adds
+    /// the formats of their headers.
+    pub fn load_overrides(&mut self, file: impl AsRef<Utf8Path>) -> Result<()> {
+        let overrides: Vec<Override> = serde_json::from_slice(&std::fs::read(file.as_ref())?)?;
+
+        for Override { pattern, header } in overrides {
+            let header = texture_file::FormatHeader::from_hexstring(&header)?;
+            let id = self.update_format(header.to(), None::<Utf8PathBuf>);
+            self.update_header(id, &header);
+
+            self.overrides.push((pattern, id));
+        }
+
+        Ok(())
+    }
+}
+
+impl ScanReport {
+    /// A registry with the new and changed formats, ready to be saved as a
+    /// user `formats.json`.
+    #[must_use]
+    pub fn updates(&self) -> Registry {
+        let mut updates = self.learned.subset(&super::FormatSelection {
+            ids: self.new.iter().chain(&self.changed).copied().collect(),
+            ..Default::default()
+        });
+        updates.overrides = self.learned.overrides.clone();
+        updates
+    }
+
+    #[must_use]
+    pub fn diff<'r>(&'r self, base: &'r Registry) -> ScanDiff<'r> {
+        ScanDiff {
+            new:       self
+                .new
+                .iter()
+                .map(|id| diff_entry(&self.learned, *id))
+                .collect(),
+            changed:   self
+                .changed
+                .iter()
+                .map(|id| ChangedEntry {
+                    id:     *id,
+                    before: diff_entry(base, *id),
+                    after:  diff_entry(&self.learned, *id),
+                })
+                .collect(),
+            conflicts: self.conflicts.iter().map(ToString::to_string).collect(),
+            unknown:   self.unknown.iter().map(|file| file.as_str()).collect(),
+        }
+    }
+}
+
+fn diff_entry(registry: &Registry, id: FormatId) -> DiffEntry<'_> {
+    DiffEntry {
+        id,
+        format: registry.get(id),
+        raw_header: registry.raw_headers.get(&id).map(String::as_str),
+        example: registry.examples.get(&id).map(String::as_str),
+    }
+}
+
+fn is_scannable(file: &Utf8Path) -> bool {
+    match file.extension() {
+        Some("texture") => (),
+        Some("raw") if !file.with_extension("texture").exists() => (),
+        _ => return false,
+    }
+
+    !file.file_name().unwrap_or_default().contains("_hd.")
+}
+
+/// Headers of array textures don't always carry the array size, so it is
+/// worked out from the data size if the data holds a whole number of images.
+#[must_use]
+pub fn guess_array_size(format: &TextureFormat, data_size: usize) -> Option<usize> {
+    if format.array_size > 1 {
+        return None;
+    }
+    let expected = dxtex::expected_size(format.dxgi_format, format.standard, 1);
+    if expected == data_size {
+        return None;
+    }
+    if data_size % expected == 0 {
+        let array_size = data_size / expected;
+        let expected = dxtex::expected_size_array(format.dxgi_format, format.standard, array_size);
+        if expected == data_size {
+            event!(DEBUG, "Array size {array_size} looks good!");
+            return Some(array_size);
+        }
+    }
+    event!(DEBUG, "No array size matches {data_size} bytes");
+    None
+}
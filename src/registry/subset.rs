@@ -0,0 +1,98 @@
+use std::collections::BTreeSet;
+
+use camino::Utf8Path;
+use regex::Regex;
+
+use super::{FormatId, Registry};
+use crate::prelude::*;
+
+/// Picks formats for a partial registry. A format is selected if it matches
+/// any of the ids, sizes or patterns.
+#[derive(Debug, Default, Clone)]
+pub struct FormatSelection {
+    pub ids:      Vec<FormatId>,
+    /// File sizes, with or without the texture header
+    pub sizes:    Vec<usize>,
+    /// Regular expressions matched against example file names
+    pub patterns: Vec<Regex>,
+}
+
+impl FormatSelection {
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty() && self.sizes.is_empty() && self.patterns.is_empty()
+    }
+
+    pub fn add_pattern(&mut self, pattern: &str) -> Result<()> {
+        let regex = Regex::new(pattern)
+            .map_err(|error| Error::message(format!("Invalid pattern {pattern}: {error}")))?;
+        self.patterns.push(regex);
+        Ok(())
+    }
+}
+
+impl Registry {
+    /// Copies the selected formats into a new registry, along with their
+    /// lengths, headers, examples, suffixes and overrides.
+    #[must_use]
+    pub fn subset(&self, selection: &FormatSelection) -> Self {
+        let ids: BTreeSet<FormatId> = self
+            .formats
+            .iter()
+            .filter(|(id, format)| {
+                selection.ids.iter().any(|selected| self.resolve(*selected) == **id)
+                    || selection.sizes.iter().any(|size| {
+                        format
+                            .dimensions_iter()
+                            .any(|dims| dims.is_for_file_size(*size))
+                    })
+                    || self.examples.get(id).map_or(false, |example| {
+                        selection.patterns.iter().any(|regex| regex.is_match(example))
+                    })
+            })
+            .map(|(id, _)| *id)
+            .collect();
+
+        let selected = |id: &FormatId| ids.contains(id);
+        let mut subset = Self::default();
+
+        for id in &ids {
+            subset.formats.insert(*id, self.formats[id]);
+            if let Some(header) = self.raw_headers.get(id) {
+                subset.raw_headers.insert(*id, header.clone());
+            }
+            if let Some(example) = self.examples.get(id) {
+                subset.examples.insert(*id, example.clone());
+            }
+        }
+        for (length, formats) in &self.lengths {
+            let formats: BTreeSet<FormatId> = formats.iter().copied().filter(selected).collect();
+            if !formats.is_empty() {
+                subset.lengths.insert(*length, formats);
+            }
+        }
+        for (suffix, formats) in &self.suffixes {
+            let formats: BTreeSet<FormatId> = formats.iter().copied().filter(selected).collect();
+            if !formats.is_empty() {
+                subset.suffixes.insert(suffix.clone(), formats);
+            }
+        }
+        subset.overrides = self
+            .overrides
+            .iter()
+            .filter(|(_, id)| selected(id))
+            .cloned()
+            .collect();
+
+        subset
+    }
+
+    pub fn save_as(&self, file: impl AsRef<Utf8Path>) -> Result<()> {
+        let json =
+            serde_json::to_string_pretty(self).log_failure_as("Failed to serialize registry")?;
+        std::fs::write(file.as_ref(), &json).log_failure_as("Failed to save registry")?;
+
+        Ok(())
+    }
+}
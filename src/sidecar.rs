@@ -0,0 +1,238 @@
+//! The `<name>.metadata.json` file written next to every export.
+//!
+//! Version 2 records everything needed to rebuild the original texture: the
+//! format and its id, the raw format header, the source files with their
+//! sizes and hashes, the options used and the SpiderTex version. Version 1
+//! files only contained the serialized [`TextureFormat`] and are still read.
+
+use std::io::Read;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+
+use crate::atlas::AtlasLayout;
+use crate::files::base_name;
+use crate::options::Options;
+use crate::prelude::*;
+use crate::util::current_dir_utf8;
+
+pub const SIDECAR_EXTENSION: &str = "metadata.json";
+pub const SIDECAR_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sidecar {
+    pub version:    u32,
+    /// The SpiderTex version that wrote the file
+    pub spidertex:  String,
+    pub id:         FormatId,
+    /// The raw format header of the source texture
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub header:     Option<String>,
+    pub format:     TextureFormat,
+    #[serde(default)]
+    pub sources:    Vec<SourceFile>,
+    #[serde(default)]
+    pub options:    Options,
+    /// Whether the images were exported as a normal map
+    #[serde(default)]
+    pub normal_map: bool,
+    /// The grid an array was exported in as one image
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub atlas:      Option<AtlasLayout>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceFile {
+    /// Path relative to the sidecar once saved, the path as given before
+    pub name:  String,
+    pub size:  u64,
+    /// CRC-32 of the whole file, as hex
+    pub crc32: String,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AnyVersion {
+    V2(Sidecar),
+    V1(TextureFormat),
+}
+
+/// The sidecar file for an exported image or its source texture.
+#[must_use]
+pub fn sidecar_file(file: &Utf8Path) -> Utf8PathBuf {
+    Utf8PathBuf::from(format!("{}.{SIDECAR_EXTENSION}", base_name(file)))
+}
+
+impl Sidecar {
+    /// Describes an export of `sources` as `format`. The raw header is taken
+    /// from the first source with a texture header, or from the registry.
+    pub fn new(format: TextureFormat, sources: &[Utf8PathBuf], options: &Options) -> Result<Self> {
+        let header = sources
+            .iter()
+            .find_map(|file| texture_file::read_header(file).ok()?.0)
+            .map(|header| header.as_hexstring())
+            .or_else(|| registry().raw_header_for(&format).map(ToOwned::to_owned));
+
+        Ok(Self {
+            version: SIDECAR_VERSION,
+            spidertex: env!("CARGO_PKG_VERSION").to_owned(),
+            id: format.id(),
+            header,
+            format,
+            sources: sources
+                .iter()
+                .map(|file| SourceFile::new(file))
+                .collect::<Result<_>>()?,
+            options: options.clone(),
+            normal_map: false,
+            atlas: None,
+        })
+    }
+
+    pub fn load(file: &Utf8Path) -> Result<Self> {
+        let data = std::fs::read(file)?;
+
+        Ok(match serde_json::from_slice(&data)? {
+            AnyVersion::V2(sidecar) => sidecar,
+            AnyVersion::V1(format) => Self {
+                version:    1,
+                spidertex:  String::new(),
+                id:         format.id(),
+                header:     None,
+                format,
+                sources:    Vec::new(),
+                options:    Options::default(),
+                normal_map: false,
+                atlas:      None,
+            },
+        })
+    }
+
+    /// Writes the sidecar to `file`, with the source paths relative to it so
+    /// that they are found wherever the images were exported to.
+    pub fn save(&self, file: &Utf8Path) -> Result<()> {
+        let dir = file.parent().unwrap_or_else(|| Utf8Path::new(""));
+        let mut sidecar = self.clone();
+        for source in &mut sidecar.sources {
+            source.name = relative_path(Utf8Path::new(&source.name), dir).into_string();
+        }
+
+        let json = serde_json::to_string_pretty(&sidecar)?;
+        std::fs::write(file, json)?;
+
+        event!(TRACE, "Saved metadata to {file}");
+        Ok(())
+    }
+
+    /// Sources of a saved `sidecar` that no longer match the recorded hashes.
+    #[must_use]
+    pub fn changed_sources(&self, sidecar: &Utf8Path) -> Vec<&SourceFile> {
+        let dir = sidecar.parent().unwrap_or_else(|| Utf8Path::new(""));
+
+        self.sources
+            .iter()
+            .filter(|source| {
+                let file = dir.join(&source.name);
+                file.exists()
+                    && SourceFile::new(&file).map_or(true, |current| {
+                        current.size != source.size || current.crc32 != source.crc32
+                    })
+            })
+            .collect()
+    }
+}
+
+impl SourceFile {
+    pub fn new(file: &Utf8Path) -> Result<Self> {
+        let mut reader = std::fs::File::open(file)?;
+        let mut hasher = crc32fast::Hasher::new();
+        let mut buffer = [0_u8; 64 * 1024];
+        let mut size = 0;
+
+        loop {
+            let read = reader.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[.. read]);
+            size += read as u64;
+        }
+
+        Ok(Self {
+            name: file.as_str().to_owned(),
+            size,
+            crc32: format!("{:08x}", hasher.finalize()),
+        })
+    }
+}
+
+/// `file` relative to `dir`. Relative paths are taken from the current
+/// directory, and `file` is returned absolute when the two share no root.
+fn relative_path(file: &Utf8Path, dir: &Utf8Path) -> Utf8PathBuf {
+    let absolute = |path: &Utf8Path| match current_dir_utf8() {
+        Some(current) if path.is_relative() => current.join(path),
+        _ => path.to_owned(),
+    };
+    let (file, dir) = (absolute(file), absolute(dir));
+
+    let common = file
+        .components()
+        .zip(dir.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    if common == 0 {
+        return file;
+    }
+
+    dir.components()
+        .skip(common)
+        .map(|_| "..")
+        .chain(file.components().skip(common).map(|part| part.as_str()))
+        .collect()
+}
+
+#[test]
+fn test_sidecar_versions() {
+    use windows::Win32::Graphics::Dxgi::Common::DXGI_FORMAT_BC7_UNORM;
+
+    registry::load().unwrap();
+
+    let format = TextureFormat {
+        dxgi_format: DXGI_FORMAT_BC7_UNORM,
+        standard:    Dimensions {
+            data_size: 1_398_128,
+            width:     1024,
+            height:    1024,
+            mipmaps:   8,
+        },
+        highres:     None,
+        array_size:  1,
+        stex_format: (0, 0),
+        source:      Default::default(),
+    };
+
+    let v1 = serde_json::to_string(&format).unwrap();
+    assert!(matches!(serde_json::from_str(&v1).unwrap(), AnyVersion::V1(_)));
+
+    let sidecar = Sidecar::new(format, &[], &Options::default()).unwrap();
+    let v2 = serde_json::to_string(&sidecar).unwrap();
+    match serde_json::from_str(&v2).unwrap() {
+        AnyVersion::V2(read) => assert_eq!(read.id, format.id()),
+        AnyVersion::V1(_) => panic!("Read a version 2 sidecar as version 1"),
+    }
+
+    assert_eq!(
+        sidecar_file(Utf8Path::new("dir/name_hd#01.png")),
+        Utf8PathBuf::from("dir/name.metadata.json")
+    );
+
+    let texture = Utf8Path::new("game/textures/name.texture");
+    assert_eq!(
+        relative_path(texture, Utf8Path::new("game/textures")),
+        Utf8PathBuf::from("name.texture")
+    );
+    assert_eq!(
+        relative_path(texture, Utf8Path::new("out/images")),
+        Utf8PathBuf::from("../../game/textures/name.texture")
+    );
+}
@@ -302,6 +302,21 @@ impl FormatHeader {
         }
     }
 
+    /// Writes the sizes, data lengths and mip counts of `format`, keeping the
+    /// other bytes. Without an HD texture both halves describe the SD one.
+    pub fn set_layout(&mut self, format: &TextureFormat) {
+        let highres = format.highres.unwrap_or(format.standard);
+
+        self.sd_len = format.standard.data_size as u32;
+        self.sd_width = format.standard.width as u16;
+        self.sd_height = format.standard.height as u16;
+        self.sd_mipmaps = format.standard.mipmaps;
+        self.hd_len = highres.data_size as u32;
+        self.hd_width = highres.width as u16;
+        self.hd_height = highres.height as u16;
+        self.hd_mipmaps = highres.mipmaps;
+    }
+
     #[inline]
     pub fn from_hexstring(hex: &str) -> Result<Self> {
         let mut bytes = hex::decode(hex)?;
@@ -384,3 +399,29 @@ impl TryFrom<&TextureFormat> for FormatHeader {
 
     fn try_from(format: &TextureFormat) -> Result<Self> { todo!() }
 }
+
+#[test]
+fn test_set_layout() {
+    let mut format = TextureFormat::from(&FormatHeader::from_hexstring("").unwrap());
+    format.standard = Dimensions {
+        data_size: 1_398_128,
+        width:     1024,
+        height:    1024,
+        mipmaps:   8,
+    };
+    format.highres = Some(Dimensions {
+        data_size: 5_242_880,
+        width:     2048,
+        height:    2048,
+        mipmaps:   2,
+    });
+
+    let mut header = FormatHeader::from_hexstring("").unwrap();
+    header.set_layout(&format);
+    assert!(TextureFormat::from(&header).same_layout(&format));
+
+    format.highres = None;
+    header.set_layout(&format);
+    assert_eq!((header.hd_width, header.hd_len), (1024, 1_398_128));
+    assert!(TextureFormat::from(&header).same_layout(&format));
+}
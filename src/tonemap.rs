@@ -0,0 +1,109 @@
+//! LDR previews of HDR textures. Pixels are scaled by the exposure, mapped
+//! into `0..1` by the operator and encoded as sRGB.
+
+use camino::{Utf8Path, Utf8PathBuf};
+use windows::Win32::Graphics::Dxgi::Common::{
+    DXGI_FORMAT_R32G32B32A32_FLOAT,
+    DXGI_FORMAT_R8G8B8A8_UNORM_SRGB,
+};
+
+use crate::dxtex::DXImage;
+use crate::options::{ToneMapOperator, ToneMapOptions};
+use crate::prelude::*;
+
+pub const PREVIEW_SUFFIX: &str = ".preview.png";
+
+/// The preview written next to an exported HDR image.
+#[must_use]
+pub fn preview_file(output_file: &Utf8Path) -> Utf8PathBuf {
+    let name = output_file.file_name().unwrap_or_default();
+    let stem = name.split_once('.').map_or(name, |(stem, _)| stem);
+
+    output_file.with_file_name(format!("{stem}{PREVIEW_SUFFIX}"))
+}
+
+/// Previews are only written, never imported.
+#[must_use]
+pub fn is_preview_file(file: &Utf8Path) -> bool {
+    file.file_name()
+        .map_or(false, |name| name.to_ascii_lowercase().ends_with(PREVIEW_SUFFIX))
+}
+
+/// Maps one linear colour channel.
+#[must_use]
+pub fn map(value: f32, operator: ToneMapOperator, exposure: f32) -> f32 {
+    let x = value.max(0.0) * exposure.exp2();
+    let mapped = match operator {
+        ToneMapOperator::Clamp => x,
+        ToneMapOperator::Reinhard => x / (1.0 + x),
+        ToneMapOperator::AcesFitted => (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14),
+    };
+    mapped.max(0.0).min(1.0)
+}
+
+#[must_use]
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Tone maps RGBA float pixels to sRGB RGBA bytes. Alpha is only clamped.
+#[must_use]
+pub fn to_rgba8(pixels: &[f32], options: &ToneMapOptions) -> Vec<u8> {
+    pixels
+        .chunks_exact(4)
+        .flat_map(|pixel| {
+            let channel = |value| {
+                let srgb = linear_to_srgb(map(value, options.operator, options.exposure));
+                (srgb * 255.0 + 0.5) as u8
+            };
+            let alpha = (pixel[3].max(0.0).min(1.0) * 255.0 + 0.5) as u8;
+            [channel(pixel[0]), channel(pixel[1]), channel(pixel[2]), alpha]
+        })
+        .collect()
+}
+
+/// An `R8G8B8A8_UNORM_SRGB` preview of the top mip level of one array slice.
+pub fn to_ldr(image: &DXImage, array_index: usize, options: &ToneMapOptions) -> Result<DXImage> {
+    let metadata = image.metadata()?;
+    let float = image.to_format(DXGI_FORMAT_R32G32B32A32_FLOAT)?;
+    let pixels: Vec<f32> = float
+        .image(array_index * metadata.mipLevels)?
+        .chunks_exact(4)
+        .map(|bytes| f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect();
+
+    DXImage::new_2d(
+        DXGI_FORMAT_R8G8B8A8_UNORM_SRGB,
+        metadata.width,
+        metadata.height,
+        1,
+        1,
+        &to_rgba8(&pixels, options),
+    )
+}
+
+#[test]
+fn test_tonemap() {
+    for operator in ToneMapOperator::ALL {
+        assert_eq!(map(0.0, operator, 0.0), 0.0);
+        assert!(map(100.0, operator, 0.0) <= 1.0);
+        assert!(map(0.5, operator, 1.0) > map(0.5, operator, 0.0));
+    }
+    assert_eq!(map(2.0, ToneMapOperator::Clamp, 0.0), 1.0);
+    assert!((map(1.0, ToneMapOperator::Reinhard, 0.0) - 0.5).abs() < 1e-6);
+
+    let options = ToneMapOptions {
+        operator: ToneMapOperator::Clamp,
+        exposure: -1.0,
+        preview:  false,
+    };
+    assert_eq!(to_rgba8(&[2.0, 0.0, 1.0, 1.5], &options), [255, 0, 188, 255]);
+
+    let preview = preview_file(Utf8Path::new("dir/sky_c#02.exr"));
+    assert_eq!(preview, Utf8PathBuf::from("dir/sky_c#02.preview.png"));
+    assert!(is_preview_file(&preview));
+}
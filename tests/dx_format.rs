@@ -1,7 +1,7 @@
 use camino::Utf8Path;
 use spidertexlib::dxtex::{self, DXImage, TexMetadata, TEX_DIMENSION, TEX_FILTER_FLAGS};
 use spidertexlib::formats::{
-    guess_dimensions_2,
+    guess_dimensions,
     probe_textures_2,
     ColorPlanes,
     ImageFormat,
@@ -127,7 +127,7 @@ fn test() -> Result<()> {
             //     probe_textures_2(&mut registry, &[file.to_owned()])?;
             // if !detected_formats.is_empty() {
             //     if let Some((dimensions, strip_header)) =
-            //         guess_dimensions_2(image_buffer.len(), &detected_formats)
+            //         guess_dimensions(image_buffer.len(), &detected_formats)
             //     {
             //         // FIXME
             //         let format = detected_formats.first().unwrap();
@@ -2,7 +2,7 @@ use camino::{Utf8Path, Utf8PathBuf};
 use spidertexlib::dxtex::{self, DXImage, TexMetadata, TEX_DIMENSION, TEX_FILTER_FLAGS};
 use spidertexlib::files::{as_images, ng_format_for_texture_file, Categorized, FileType};
 use spidertexlib::formats::{
-    guess_dimensions_2,
+    guess_dimensions,
     probe_textures_2,
     ColorPlanes,
     ImageFormat,